@@ -2,8 +2,12 @@
 // The absolute bare minimum to get it working, and test that it works.
 
 use pcsc::*;
-use scannit_core::desfire::{Command, Response};
-use scannit_core::travelcard::create_travel_card;
+use scannit_core::analytics::{analyze, Period};
+use scannit_core::desfire::applications::{read_application_ids, CardIdentification};
+use scannit_core::desfire::version::CardVersion;
+use scannit_core::desfire::CardTransport;
+use scannit_core::reader::read_travel_card;
+use std::ffi::CStr;
 
 fn main() {
     let ctx = match Context::establish(Scope::User) {
@@ -32,89 +36,111 @@ fn main() {
         }
     };
 
-    // Connect to the card
-    let card = match ctx.connect(reader, ShareMode::Shared, Protocols::ANY) {
-        Ok(card) => card,
-        Err(Error::NoSmartcard) => {
-            println!("No smart card present.");
-            return;
+    let card = match connect(&ctx, reader) {
+        Some(card) => card,
+        None => return,
+    };
+
+    let mut transport = PcscTransport { card: &card };
+    match CardVersion::read(&mut transport) {
+        Ok(version) => println!(
+            "Card is a DESFire {:?}, {} bytes, UID {:02X?}",
+            version.generation(),
+            version.memory_size_bytes(),
+            version.uid
+        ),
+        Err(err) => println!("Failed to read card version: {}", err),
+    }
+
+    match read_application_ids(&mut transport) {
+        Ok(ids) => {
+            let identification = CardIdentification::from_application_ids(&ids);
+            if !identification.is_hsl() {
+                println!("{}.", identification);
+                return;
+            }
         }
+        Err(err) => println!("Failed to read application IDs: {}", err),
+    }
+
+    let travel_card = match read_travel_card(&mut transport) {
+        Ok(travel_card) => travel_card,
         Err(err) => {
-            eprintln!("Failed to connect to card: {}", err);
-            std::process::exit(1);
+            println!("Failed to read the travel card: {}", err);
+            return;
         }
     };
 
-    let open_hsl_command = Command::SelectHsl.into();
-    println!("Sending APDU: {:X?}", open_hsl_command);
-    let mut response_buffer = [0; MAX_BUFFER_SIZE];
+    println!("Travel card all read! Values: {:?}", travel_card);
 
-    let result = transcieve(&card, open_hsl_command, &mut response_buffer).unwrap();
+    let analytics = analyze(&travel_card.history, Period::Month);
+    println!("Month       Trips  Season  Value  Spent (€)");
+    for month in analytics.periods {
+        println!(
+            "{:<10}  {:>5}  {:>6}  {:>5}  {:>9.2}",
+            month.start.format("%Y-%m"),
+            month.stats.trips,
+            month.stats.season_pass_trips,
+            month.stats.value_ticket_trips,
+            f64::from(month.stats.spend_cents) / 100.0
+        );
+    }
 
-    if result[0..2] != Response::Ok {
-        println!("Failed. Received {:X?}", result);
+    // With --diff, read the card a second time and show what changed in between.
+    if !std::env::args().any(|arg| arg == "--diff") {
         return;
     }
-
-    println!("Success! Reading data...");
-
-    let app_info = transcieve(&card, Command::ReadAppInfo.into(), &mut response_buffer).unwrap();
-
-    let mut response_buffer = [0; MAX_BUFFER_SIZE];
-    let control_info =
-        transcieve(&card, Command::ReadControlInfo.into(), &mut response_buffer).unwrap();
-
-    let mut response_buffer = [0; MAX_BUFFER_SIZE];
-    let period_pass =
-        transcieve(&card, Command::ReadPeriodPass.into(), &mut response_buffer).unwrap();
-
-    let mut response_buffer = [0; MAX_BUFFER_SIZE];
-    let stored_value =
-        transcieve(&card, Command::ReadStoredValue.into(), &mut response_buffer).unwrap();
-
-    let mut response_buffer = [0; MAX_BUFFER_SIZE];
-    let e_ticket = transcieve(&card, Command::ReadETicket.into(), &mut response_buffer).unwrap();
-
-    let mut response_buffer = [0; MAX_BUFFER_SIZE];
-    let history_bytes =
-        transcieve(&card, Command::ReadHistory.into(), &mut response_buffer).unwrap();
-    let history_bytes_2: Vec<u8>;
-
-    // Check for extra history data. It comes in chunks of four.
-    let len = history_bytes.len();
-    // If the last two bytes of history contain MORE_DATA:
-    let all_history: &[u8] = if history_bytes[len - 2..len] == Response::MoreData {
-        let mut response_buffer = [0; MAX_BUFFER_SIZE];
-        let bytes = transcieve(&card, Command::ReadNext.into(), &mut response_buffer).unwrap();
-        let len_2 = bytes.len();
-        history_bytes_2 = [&history_bytes[0..len - 2], &bytes[0..len_2 - 2]].concat();
-        &history_bytes_2
-    } else {
-        &history_bytes[0..len - 2]
+    println!("Use the card, then present it again and press Enter to see what changed.");
+    if std::io::stdin().read_line(&mut String::new()).is_err() {
+        return;
+    }
+    let card = match connect(&ctx, reader) {
+        Some(card) => card,
+        None => return,
     };
+    let current = match read_travel_card(&mut PcscTransport { card: &card }) {
+        Ok(travel_card) => travel_card,
+        Err(err) => {
+            println!("Failed to read the travel card: {}", err);
+            return;
+        }
+    };
+    match current.diff(&travel_card) {
+        Ok(diff) => print!("{}", diff),
+        Err(err) => println!("Can't compare the two reads: {}", err),
+    }
+}
 
-    let travel_card = create_travel_card(
-        app_info,
-        control_info,
-        period_pass,
-        stored_value,
-        e_ticket,
-        all_history,
-    );
+/// Connects to the card on the given reader. None if there's no card.
+fn connect(ctx: &Context, reader: &CStr) -> Option<Card> {
+    match ctx.connect(reader, ShareMode::Shared, Protocols::ANY) {
+        Ok(card) => Some(card),
+        Err(Error::NoSmartcard) => {
+            println!("No smart card present.");
+            None
+        }
+        Err(err) => {
+            eprintln!("Failed to connect to card: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
 
-    println!("Travel card all read! Values: {:?}", travel_card);
+struct PcscTransport<'a> {
+    card: &'a pcsc::Card,
 }
 
-fn transcieve<'a>(
-    card: &pcsc::Card,
-    command: &[u8],
-    response_buffer: &'a mut [u8],
-) -> Result<&'a [u8], Error> {
-    match card.transmit(command, response_buffer) {
-        Ok(res) => Ok(res),
-        Err(err) => {
-            eprintln!("Failed to transmit command to card: {}", err);
-            Err(err)
+impl CardTransport for PcscTransport<'_> {
+    type Error = Error;
+
+    fn transceive(&mut self, command: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut response_buffer = [0; MAX_BUFFER_SIZE];
+        match self.card.transmit(command, &mut response_buffer) {
+            Ok(res) => Ok(res.to_vec()),
+            Err(err) => {
+                eprintln!("Failed to transmit command to card: {}", err);
+                Err(err)
+            }
         }
     }
 }
@@ -10,6 +10,9 @@ use scannit_core::desfire::{
 };
 use scannit_core::travelcard;
 
+/// # Safety
+/// Each pointer must point to a readable buffer of at least its paired size.
+/// The returned card must be released with `free_travel_card`.
 #[no_mangle]
 pub unsafe extern "C" fn create_travel_card(
     app_info_ptr: *const u8,
@@ -25,20 +28,13 @@ pub unsafe extern "C" fn create_travel_card(
     history_ptr: *const u8,
     history_size: usize,
 ) -> *mut FFITravelCard {
-    let app_info;
-    let control_info;
-    let period_pass;
-    let stored_value;
-    let e_ticket;
-    let history;    
-
     // Actual unsafety begins here
-    app_info = std::slice::from_raw_parts(app_info_ptr, app_info_size);
-    control_info = std::slice::from_raw_parts(control_info_ptr, control_info_size);
-    period_pass = std::slice::from_raw_parts(period_pass_ptr, period_pass_size);
-    stored_value = std::slice::from_raw_parts(stored_value_ptr, stored_value_size);
-    e_ticket = std::slice::from_raw_parts(e_ticket_ptr, e_ticket_size);
-    history = std::slice::from_raw_parts(history_ptr, history_size);
+    let app_info = std::slice::from_raw_parts(app_info_ptr, app_info_size);
+    let control_info = std::slice::from_raw_parts(control_info_ptr, control_info_size);
+    let period_pass = std::slice::from_raw_parts(period_pass_ptr, period_pass_size);
+    let stored_value = std::slice::from_raw_parts(stored_value_ptr, stored_value_size);
+    let e_ticket = std::slice::from_raw_parts(e_ticket_ptr, e_ticket_size);
+    let history = std::slice::from_raw_parts(history_ptr, history_size);
     // Unsafety ends here
 
     let travelcard = travelcard::create_travel_card(
@@ -54,19 +50,13 @@ pub unsafe extern "C" fn create_travel_card(
     Box::into_raw(Box::from(ffi_travel_card))
 }
 
+/// # Safety
+/// `travel_card_ptr` must have been returned by `create_travel_card`, and must not be used afterward.
 #[no_mangle]
 pub unsafe extern "C" fn free_travel_card(travel_card_ptr: *mut FFITravelCard) {
     let travel_card = Box::from_raw(travel_card_ptr);
     ffi::free_string(travel_card.application_instance_id);
     ffi::free_history_buffer(travel_card.history);
-
-    ffi::free_byte_buffer(travel_card.period_pass.validity_area_1_value);
-    ffi::free_byte_buffer(travel_card.period_pass.validity_area_2_value);
-
-    ffi::free_byte_buffer(travel_card.e_ticket.validity_area_value);
-    ffi::free_byte_buffer(travel_card.e_ticket.period_pass_validity_area_value);
-    ffi::free_byte_buffer(travel_card.e_ticket.extension_1_validity_area_value);
-    ffi::free_byte_buffer(travel_card.e_ticket.extension_2_validity_area_value);
 }
 
 // The following don't need free() functions, because they're constant--
@@ -135,4 +125,4 @@ pub extern "C" fn get_ERROR_RESPONSE() -> *const u8 {
 #[no_mangle]
 pub extern "C" fn get_MORE_DATA_RESPONSE() -> *const u8 {
     MORE_DATA_RESPONSE.as_ptr()
-}
\ No newline at end of file
+}
@@ -1,16 +1,28 @@
-use crate::ffi::{FFIByteBuffer, FFIHistoryBuffer};
+use crate::ffi::FFIHistoryBuffer;
+use chrono::{Duration, NaiveDate, NaiveTime};
 use libc::c_char;
 use scannit_core::eticket::ETicket;
 use scannit_core::history::{History, TransactionType};
 use scannit_core::models::{
-    BoardingArea, BoardingDirection, BoardingLocation, Language, ProductCode, SaleDevice,
-    ValidityArea, ValidityLength,
+    BoardingArea, BoardingDirection, BoardingLocation, CustomerProfile, Language, ProductCode,
+    SaleDevice, ValidityArea, ValidityLength,
 };
 use scannit_core::travelcard::{PeriodPass, TravelCard};
 use std::ffi::CString;
 
 pub type UnixTimestamp = i64;
 
+/// Calendar dates are projected as midnight UTC on the UTC date that Helsinki midnight
+/// falls on, i.e. the day before. This is what period dates have always been exported as,
+/// so existing consumers keep getting the same values.
+fn date_to_timestamp(date: NaiveDate) -> UnixTimestamp {
+    // Helsinki is always ahead of UTC, so its midnight is always on the previous UTC day.
+    (date - Duration::days(1))
+        .and_time(NaiveTime::MIN)
+        .and_utc()
+        .timestamp()
+}
+
 /// An FFI-friendly version of a travel card. Note that all dynamically-allocated members
 /// in this struct have already had their memory leaked. It is the responsibility of the
 /// external consumer to manually return this to Rust code to be freed.
@@ -78,18 +90,18 @@ pub struct FFIPeriodPass {
     pub product_code_1_kind: ProductCodeKind,
     pub product_code_1_value: u16,
     pub validity_area_1_kind: ValidityAreaKind,
-    /// This is either a single positive integer, or short list of positive integers.
-    /// We'll represent it as something that's always an array.
-    pub validity_area_1_value: FFIByteBuffer,
+    /// For `OldZone` and `VehicleType`, the region or vehicle type number. For `NewZone`,
+    /// a bitmask of the zones, where bit N is set for zone N (A = 0).
+    pub validity_area_1_value: u8,
     pub period_start_date_1: UnixTimestamp,
     pub period_end_date_1: UnixTimestamp,
 
     pub product_code_2_kind: ProductCodeKind,
     pub product_code_2_value: u16,
     pub validity_area_2_kind: ValidityAreaKind,
-    /// This is either a single positive integer, or short list of positive integers.
-    /// We'll represent it as something that's always an array.
-    pub validity_area_2_value: FFIByteBuffer,
+    /// For `OldZone` and `VehicleType`, the region or vehicle type number. For `NewZone`,
+    /// a bitmask of the zones, where bit N is set for zone N (A = 0).
+    pub validity_area_2_value: u8,
     pub period_start_date_2: UnixTimestamp,
     pub period_end_date_2: UnixTimestamp,
 
@@ -110,6 +122,8 @@ pub struct FFIPeriodPass {
     pub last_board_location_value: u16,
     pub last_board_direction: BoardingDirection,
     pub last_board_area_kind: BoardingAreaKind,
+    /// For `Zone` and `Vehicle`, the zone or vehicle type number. For `ZoneCircle`,
+    /// a bitmask of the zones in the circle, where bit N is set for zone N (A = 0).
     pub last_board_area_value: u8,
 }
 
@@ -119,16 +133,16 @@ impl FFIPeriodPass {
             product_code_1_kind: ProductCodeKind::from(&period_pass.product_code_1),
             product_code_1_value: u16::from(&period_pass.product_code_1),
             validity_area_1_kind: ValidityAreaKind::from(&period_pass.validity_area_1),
-            validity_area_1_value: FFIByteBuffer::from(period_pass.validity_area_1),
-            period_start_date_1: period_pass.period_start_date_1.and_hms(0, 0, 0).timestamp(),
-            period_end_date_1: period_pass.period_end_date_1.and_hms(0, 0, 0).timestamp(),
+            validity_area_1_value: validity_area_value(&period_pass.validity_area_1),
+            period_start_date_1: date_to_timestamp(period_pass.period_start_date_1),
+            period_end_date_1: date_to_timestamp(period_pass.period_end_date_1),
 
             product_code_2_kind: ProductCodeKind::from(&period_pass.product_code_2),
             product_code_2_value: u16::from(&period_pass.product_code_2),
             validity_area_2_kind: ValidityAreaKind::from(&period_pass.validity_area_2),
-            validity_area_2_value: FFIByteBuffer::from(period_pass.validity_area_2),
-            period_start_date_2: period_pass.period_start_date_2.and_hms(0, 0, 0).timestamp(),
-            period_end_date_2: period_pass.period_end_date_2.and_hms(0, 0, 0).timestamp(),
+            validity_area_2_value: validity_area_value(&period_pass.validity_area_2),
+            period_start_date_2: date_to_timestamp(period_pass.period_start_date_2),
+            period_end_date_2: date_to_timestamp(period_pass.period_end_date_2),
 
             loaded_period_product_kind: ProductCodeKind::from(&period_pass.loaded_period_product),
             loaded_period_product_value: u16::from(&period_pass.loaded_period_product),
@@ -144,7 +158,7 @@ impl FFIPeriodPass {
             last_board_location_value: u16::from(&period_pass.last_board_location),
             last_board_direction: period_pass.last_board_direction,
             last_board_area_kind: BoardingAreaKind::from(&period_pass.last_board_area),
-            last_board_area_value: u8::from(&period_pass.last_board_area),
+            last_board_area_value: boarding_area_value(&period_pass.last_board_area),
         }
     }
 }
@@ -153,14 +167,18 @@ impl FFIPeriodPass {
 pub struct FFIETicket {
     pub product_code_kind: ProductCodeKind,
     pub product_code_value: u16,
-    pub customer_profile: u8,
+    pub customer_profile_kind: CustomerProfileKind,
+    /// The raw customer profile value, kept so that unknown profiles can still be shown.
+    pub customer_profile_value: u8,
+    /// True if the customer profile came from the ticket's group profile field.
+    pub customer_profile_is_group: bool,
     pub language: Language,
     pub validity_length_kind: ValidityLengthKind,
     pub validity_length_value: u8,
     pub validity_area_kind: ValidityAreaKind,
-    /// This is either a single positive integer, or short list of positive integers.
-    /// We'll represent it as something that's always an array.
-    pub validity_area_value: FFIByteBuffer,
+    /// For `OldZone` and `VehicleType`, the region or vehicle type number. For `NewZone`,
+    /// a bitmask of the zones, where bit N is set for zone N (A = 0).
+    pub validity_area_value: u8,
     pub sale_datetime: UnixTimestamp,
     pub sale_device_kind: SaleDeviceKind,
     pub sale_device_value: u16,
@@ -171,20 +189,20 @@ pub struct FFIETicket {
     pub extra_zone: bool,
     /// The validity area for the PeriodPass associated with the extra zone ticket.
     pub period_pass_validity_area_kind: ValidityAreaKind,
-    /// This is either a single positive integer, or short list of positive integers.
-    /// We'll represent it as something that's always an array.
-    pub period_pass_validity_area_value: FFIByteBuffer,
+    /// For `OldZone` and `VehicleType`, the region or vehicle type number. For `NewZone`,
+    /// a bitmask of the zones, where bit N is set for zone N (A = 0).
+    pub period_pass_validity_area_value: u8,
     pub extension_product_code_kind: ProductCodeKind,
     pub extension_product_code_value: u16,
     pub extension_1_validity_area_kind: ValidityAreaKind,
-    /// This is either a single positive integer, or short list of positive integers.
-    /// We'll represent it as something that's always an array.
-    pub extension_1_validity_area_value: FFIByteBuffer,
+    /// For `OldZone` and `VehicleType`, the region or vehicle type number. For `NewZone`,
+    /// a bitmask of the zones, where bit N is set for zone N (A = 0).
+    pub extension_1_validity_area_value: u8,
     pub extension_1_fare_cents: u16,
     pub extension_2_validity_area_kind: ValidityAreaKind,
-    /// This is either a single positive integer, or short list of positive integers.
-    /// We'll represent it as something that's always an array.
-    pub extension_2_validity_area_value: FFIByteBuffer,
+    /// For `OldZone` and `VehicleType`, the region or vehicle type number. For `NewZone`,
+    /// a bitmask of the zones, where bit N is set for zone N (A = 0).
+    pub extension_2_validity_area_value: u8,
     pub extension_2_fare_cents: u16,
     pub sale_status: bool,
 
@@ -200,6 +218,8 @@ pub struct FFIETicket {
     pub boarding_location_value: u16,
     pub boarding_direction: BoardingDirection,
     pub boarding_area_kind: BoardingAreaKind,
+    /// For `Zone` and `Vehicle`, the zone or vehicle type number. For `ZoneCircle`,
+    /// a bitmask of the zones in the circle, where bit N is set for zone N (A = 0).
     pub boarding_area_value: u8,
 }
 
@@ -208,12 +228,14 @@ impl FFIETicket {
         FFIETicket {
             product_code_kind: ProductCodeKind::from(&e_ticket.product_code),
             product_code_value: u16::from(&e_ticket.product_code),
-            customer_profile: e_ticket.customer_profile,
+            customer_profile_kind: CustomerProfileKind::from(&e_ticket.customer_profile.profile()),
+            customer_profile_value: u8::from(&e_ticket.customer_profile.profile()),
+            customer_profile_is_group: e_ticket.customer_profile.is_group(),
             language: e_ticket.language,
             validity_length_kind: ValidityLengthKind::from(&e_ticket.validity_length),
             validity_length_value: u8::from(&e_ticket.validity_length),
             validity_area_kind: ValidityAreaKind::from(&e_ticket.validity_area),
-            validity_area_value: FFIByteBuffer::from(e_ticket.validity_area),
+            validity_area_value: validity_area_value(&e_ticket.validity_area),
             sale_datetime: e_ticket.sale_datetime.timestamp(),
             sale_device_kind: SaleDeviceKind::from(&e_ticket.sale_device),
             sale_device_value: u16::from(&e_ticket.sale_device),
@@ -224,23 +246,23 @@ impl FFIETicket {
             period_pass_validity_area_kind: ValidityAreaKind::from(
                 &e_ticket.period_pass_validity_area,
             ),
-            period_pass_validity_area_value: FFIByteBuffer::from(
-                e_ticket.period_pass_validity_area,
+            period_pass_validity_area_value: validity_area_value(
+                &e_ticket.period_pass_validity_area,
             ),
             extension_product_code_kind: ProductCodeKind::from(&e_ticket.extension_product_code),
             extension_product_code_value: u16::from(&e_ticket.extension_product_code),
             extension_1_validity_area_kind: ValidityAreaKind::from(
                 &e_ticket.extension_1_validity_area,
             ),
-            extension_1_validity_area_value: FFIByteBuffer::from(
-                e_ticket.extension_1_validity_area,
+            extension_1_validity_area_value: validity_area_value(
+                &e_ticket.extension_1_validity_area,
             ),
             extension_1_fare_cents: e_ticket.extension_1_fare_cents,
             extension_2_validity_area_kind: ValidityAreaKind::from(
                 &e_ticket.extension_2_validity_area,
             ),
-            extension_2_validity_area_value: FFIByteBuffer::from(
-                e_ticket.extension_2_validity_area,
+            extension_2_validity_area_value: validity_area_value(
+                &e_ticket.extension_2_validity_area,
             ),
             extension_2_fare_cents: e_ticket.extension_2_fare_cents,
             sale_status: e_ticket.sale_status,
@@ -254,7 +276,7 @@ impl FFIETicket {
             boarding_location_value: u16::from(&e_ticket.boarding_location),
             boarding_direction: e_ticket.boarding_direction,
             boarding_area_kind: BoardingAreaKind::from(&e_ticket.boarding_area),
-            boarding_area_value: u8::from(&e_ticket.boarding_area),
+            boarding_area_value: boarding_area_value(&e_ticket.boarding_area),
         }
     }
 }
@@ -282,35 +304,17 @@ impl FFIHistory {
     }
 }
 
-impl From<ValidityArea> for FFIByteBuffer {
-    fn from(val: ValidityArea) -> Self {
-        match val {
-            ValidityArea::OldZone(zone_num) => {
-                let mut zone_nums_vec = vec![zone_num];
-                let ffi_buffer = FFIByteBuffer::from(&mut zone_nums_vec);
-                std::mem::forget(zone_nums_vec);
-                ffi_buffer
-            }
-            ValidityArea::Vehicle(vehicle_type) => {
-                let mut vehicle_nums_vec = vec![u8::from(&vehicle_type)];
-                let ffi_buffer = FFIByteBuffer::from(&mut vehicle_nums_vec);
-                std::mem::forget(vehicle_nums_vec);
-                ffi_buffer
-            }
-            ValidityArea::Zone(zones) => {
-                let mut zones_vec: Vec<u8> = zones.iter().map(u8::from).collect();
-                let ffi_buffer = FFIByteBuffer::from(&mut zones_vec);
-                std::mem::forget(zones_vec);
-                ffi_buffer
-            }
-        }
+fn validity_area_value(val: &ValidityArea) -> u8 {
+    match val {
+        ValidityArea::OldZone(old_zone) => u8::from(old_zone),
+        ValidityArea::Vehicle(vehicle_type) => u8::from(vehicle_type),
+        ValidityArea::Zone(zones) => zones.bits(),
     }
 }
 
 impl From<Vec<History>> for FFIHistoryBuffer {
     fn from(val: Vec<History>) -> Self {
-        let mut ffi_histories: Vec<FFIHistory> =
-            val.iter().map(|x| FFIHistory::from_history(x)).collect();
+        let mut ffi_histories: Vec<FFIHistory> = val.iter().map(FFIHistory::from_history).collect();
         let ffi_buffer = FFIHistoryBuffer::from(&mut ffi_histories);
         std::mem::forget(ffi_histories);
         ffi_buffer
@@ -427,6 +431,13 @@ pub enum BoardingAreaKind {
     ZoneCircle = 2,
 }
 
+fn boarding_area_value(val: &BoardingArea) -> u8 {
+    match val {
+        BoardingArea::ZoneCircle(circle) => circle.zones().bits(),
+        other => u8::from(other),
+    }
+}
+
 impl From<&BoardingArea> for BoardingAreaKind {
     fn from(val: &BoardingArea) -> Self {
         match val {
@@ -436,3 +447,48 @@ impl From<&BoardingArea> for BoardingAreaKind {
         }
     }
 }
+
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum CustomerProfileKind {
+    Unknown = 0,
+    Adult = 1,
+    Child = 2,
+    Student = 3,
+    Senior = 4,
+    ReducedMobility = 5,
+    Conscript = 6,
+    Youth = 7,
+}
+
+impl From<&CustomerProfile> for CustomerProfileKind {
+    fn from(val: &CustomerProfile) -> Self {
+        match val {
+            CustomerProfile::Adult => CustomerProfileKind::Adult,
+            CustomerProfile::Child => CustomerProfileKind::Child,
+            CustomerProfile::Student => CustomerProfileKind::Student,
+            CustomerProfile::Senior => CustomerProfileKind::Senior,
+            CustomerProfile::ReducedMobility => CustomerProfileKind::ReducedMobility,
+            CustomerProfile::Conscript => CustomerProfileKind::Conscript,
+            CustomerProfile::Youth => CustomerProfileKind::Youth,
+            CustomerProfile::Unknown(_) => CustomerProfileKind::Unknown,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::models::date_to_timestamp;
+    use chrono::{NaiveDate, TimeZone, Utc};
+
+    #[test]
+    fn period_dates_should_keep_their_exported_timestamps() {
+        let summer = NaiveDate::from_ymd_opt(2020, 6, 1).unwrap();
+        let expected = Utc.with_ymd_and_hms(2020, 5, 31, 0, 0, 0).unwrap();
+        assert_eq!(date_to_timestamp(summer), expected.timestamp());
+
+        let winter = NaiveDate::from_ymd_opt(1997, 1, 1).unwrap();
+        let expected = Utc.with_ymd_and_hms(1996, 12, 31, 0, 0, 0).unwrap();
+        assert_eq!(date_to_timestamp(winter), expected.timestamp());
+    }
+}
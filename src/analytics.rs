@@ -0,0 +1,191 @@
+//! Ridership and spending statistics over a card's history.
+
+use crate::en1545date::helsinki_date;
+use crate::history::{History, TransactionType};
+use chrono::prelude::*;
+use serde::Serialize;
+
+/// How trips are bucketed. Buckets are Helsinki calendar periods.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Period {
+    Day,
+    /// ISO weeks, starting on Monday.
+    Week,
+    Month,
+}
+
+impl Period {
+    /// The first day of the period the given date falls in.
+    pub fn start_of(self, date: NaiveDate) -> NaiveDate {
+        match self {
+            Period::Day => date,
+            Period::Week => {
+                date - chrono::Duration::days(i64::from(date.weekday().num_days_from_monday()))
+            }
+            Period::Month => date.with_day(1).unwrap(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize)]
+pub struct TripStats {
+    pub trips: u32,
+    pub season_pass_trips: u32,
+    pub value_ticket_trips: u32,
+    /// Total paid from the stored value.
+    pub spend_cents: u32,
+    /// The average value ticket fare. None if there were no value ticket trips.
+    pub average_fare_cents: Option<u32>,
+}
+
+impl TripStats {
+    fn add(&mut self, entry: &History) {
+        self.trips += 1;
+        match entry.transaction_type {
+            TransactionType::SeasonPass => self.season_pass_trips += 1,
+            TransactionType::ValueTicket => {
+                self.value_ticket_trips += 1;
+                self.spend_cents += u32::from(entry.ticket_fare_cents);
+            }
+        }
+        self.average_fare_cents = match self.value_ticket_trips {
+            0 => None,
+            n => Some(self.spend_cents / n),
+        };
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+pub struct PeriodStats {
+    /// The first day of the period.
+    pub start: NaiveDate,
+    pub stats: TripStats,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TripAnalytics {
+    pub period: Period,
+    /// Only periods with at least one trip, oldest first.
+    pub periods: Vec<PeriodStats>,
+    pub totals: TripStats,
+}
+
+/// Removes entries that appear more than once, e.g. when the history of several reads
+/// of the same card is combined, and sorts the rest oldest first.
+pub fn merge_history<'a, I>(history: I) -> Vec<&'a History>
+where
+    I: IntoIterator<Item = &'a History>,
+{
+    let mut merged: Vec<&History> = history.into_iter().collect();
+    // Sort on every field, so identical entries end up next to each other.
+    merged.sort_by_key(|h| {
+        (
+            h.boarding_datetime,
+            h.transfer_end_datetime,
+            h.transaction_type as u32,
+            h.ticket_fare_cents,
+            h.group_size,
+            h.remaining_value,
+        )
+    });
+    merged.dedup();
+    merged
+}
+
+/// Computes trip and spending statistics, bucketed by the given period. Duplicate
+/// entries are only counted once, so the history of several reads can be passed in at once.
+pub fn analyze<'a, I>(history: I, period: Period) -> TripAnalytics
+where
+    I: IntoIterator<Item = &'a History>,
+{
+    let mut periods: Vec<PeriodStats> = vec![];
+    let mut totals = TripStats::default();
+    for entry in merge_history(history) {
+        let start = period.start_of(helsinki_date(entry.boarding_datetime));
+        match periods.last_mut() {
+            Some(last) if last.start == start => last.stats.add(entry),
+            _ => {
+                let mut stats = TripStats::default();
+                stats.add(entry);
+                periods.push(PeriodStats { start, stats });
+            }
+        }
+        totals.add(entry);
+    }
+
+    TripAnalytics {
+        period,
+        periods,
+        totals,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::analytics::{analyze, Period};
+    use crate::history::TransactionType;
+    use crate::test_cards::{helsinki, trip};
+    use chrono::prelude::*;
+
+    #[test]
+    fn should_bucket_by_helsinki_week_and_skip_duplicates() {
+        let first_read = vec![
+            // Sunday, 21:30 UTC.
+            trip(
+                TransactionType::ValueTicket,
+                helsinki(2020, 3, 1, 23, 30),
+                280,
+                0,
+            ),
+            // Monday in Helsinki, but still Sunday in UTC.
+            trip(
+                TransactionType::ValueTicket,
+                helsinki(2020, 3, 2, 0, 30),
+                420,
+                0,
+            ),
+        ];
+        let mut second_read = first_read.clone();
+        second_read.push(trip(
+            TransactionType::SeasonPass,
+            helsinki(2020, 3, 3, 8, 0),
+            0,
+            0,
+        ));
+
+        let analytics = analyze(first_read.iter().chain(second_read.iter()), Period::Week);
+        assert_eq!(analytics.totals.trips, 3);
+        assert_eq!(analytics.totals.season_pass_trips, 1);
+        assert_eq!(analytics.totals.spend_cents, 700);
+        assert_eq!(analytics.totals.average_fare_cents, Some(350));
+
+        assert_eq!(analytics.periods.len(), 2);
+        assert_eq!(
+            analytics.periods[0].start,
+            NaiveDate::from_ymd_opt(2020, 2, 24).unwrap()
+        );
+        assert_eq!(analytics.periods[0].stats.trips, 1);
+        assert_eq!(
+            analytics.periods[1].start,
+            NaiveDate::from_ymd_opt(2020, 3, 2).unwrap()
+        );
+        assert_eq!(analytics.periods[1].stats.trips, 2);
+        assert_eq!(analytics.periods[1].stats.value_ticket_trips, 1);
+    }
+
+    #[test]
+    fn should_serialize_cleanly() {
+        let history = vec![trip(
+            TransactionType::ValueTicket,
+            helsinki(2020, 3, 2, 8, 0),
+            280,
+            0,
+        )];
+        let json = serde_json::to_string(&analyze(&history, Period::Month)).unwrap();
+        assert_eq!(
+            json,
+            r#"{"period":"month","periods":[{"start":"2020-03-01","stats":{"trips":1,"season_pass_trips":0,"value_ticket_trips":1,"spend_cents":280,"average_fare_cents":280}}],"totals":{"trips":1,"season_pass_trips":0,"value_ticket_trips":1,"spend_cents":280,"average_fare_cents":280}}"#
+        );
+    }
+}
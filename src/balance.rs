@@ -0,0 +1,248 @@
+use crate::history::TransactionType;
+use crate::travelcard::TravelCard;
+use chrono::prelude::*;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum BalanceStepKind {
+    /// A value ticket was paid for from the stored value.
+    Debit,
+    /// Value was loaded onto the card.
+    Load,
+}
+
+/// A single change in the card's stored value.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BalanceStep {
+    /// When the change happened. For inferred loads the real time isn't known, so this is
+    /// the time of the last event before the load.
+    pub time: DateTime<Utc>,
+    pub kind: BalanceStepKind,
+    pub amount_cents: u32,
+    pub balance_after_cents: u32,
+    /// True if this step isn't recorded on the card, but was deduced from a gap in the balance.
+    pub inferred: bool,
+}
+
+/// Something in the card's data that doesn't add up.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BalanceInconsistency {
+    /// The balance dropped by more than the recorded fares account for, sometime after the given time.
+    UnexplainedDecrease {
+        time: DateTime<Utc>,
+        missing_cents: u32,
+    },
+    /// The card's last load is larger than the balance right after it.
+    LoadExceedsBalance {
+        time: DateTime<Utc>,
+        load_cents: u32,
+        balance_after_cents: u32,
+    },
+    /// A load had to be inferred after the card's recorded last load, which should be the most recent one.
+    LoadAfterLastLoad { time: DateTime<Utc> },
+}
+
+/// The card's stored value over time, oldest step first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BalanceTimeline {
+    /// The balance before the first step.
+    pub opening_balance_cents: u32,
+    pub steps: Vec<BalanceStep>,
+    pub inconsistencies: Vec<BalanceInconsistency>,
+}
+
+enum Event {
+    Debit {
+        time: DateTime<Utc>,
+        fare_cents: u32,
+        remaining_cents: u32,
+    },
+    Load {
+        time: DateTime<Utc>,
+        value_cents: u32,
+    },
+}
+
+impl Event {
+    fn time(&self) -> DateTime<Utc> {
+        match self {
+            Event::Debit { time, .. } | Event::Load { time, .. } => *time,
+        }
+    }
+}
+
+impl TravelCard {
+    /// Reconstructs the stored value's history from the value ticket history entries
+    /// and the last load.
+    ///
+    /// The timeline is anchored at the card's current balance and worked out backwards,
+    /// trusting the remaining value recorded with each history entry. Wherever the balance
+    /// rose without a recorded load, an inferred load is added. Anything that can't be
+    /// explained is listed in `inconsistencies`.
+    pub fn balance_timeline(&self) -> BalanceTimeline {
+        let mut events: Vec<Event> = self
+            .history
+            .iter()
+            .filter(|h| h.transaction_type == TransactionType::ValueTicket)
+            .map(|h| Event::Debit {
+                time: h.boarding_datetime,
+                fare_cents: u32::from(h.ticket_fare_cents),
+                remaining_cents: h.remaining_value,
+            })
+            .collect();
+        if self.last_load_value > 0 {
+            events.push(Event::Load {
+                time: self.last_load_datetime,
+                value_cents: self.last_load_value,
+            });
+        }
+        // Loads go first on ties, as a ride paid right after loading is the likelier order.
+        events.sort_by_key(|e| (e.time(), matches!(e, Event::Debit { .. })));
+
+        let mut steps = vec![];
+        let mut inconsistencies = vec![];
+        let mut balance_after = self.stored_value_cents;
+        for event in events.iter().rev() {
+            match *event {
+                Event::Debit {
+                    time,
+                    fare_cents,
+                    remaining_cents,
+                } => {
+                    if remaining_cents < balance_after {
+                        if self.last_load_value > 0 && time >= self.last_load_datetime {
+                            inconsistencies.push(BalanceInconsistency::LoadAfterLastLoad { time });
+                        }
+                        steps.push(BalanceStep {
+                            time,
+                            kind: BalanceStepKind::Load,
+                            amount_cents: balance_after - remaining_cents,
+                            balance_after_cents: balance_after,
+                            inferred: true,
+                        });
+                    } else if remaining_cents > balance_after {
+                        inconsistencies.push(BalanceInconsistency::UnexplainedDecrease {
+                            time,
+                            missing_cents: remaining_cents - balance_after,
+                        });
+                    }
+                    steps.push(BalanceStep {
+                        time,
+                        kind: BalanceStepKind::Debit,
+                        amount_cents: fare_cents,
+                        balance_after_cents: remaining_cents,
+                        inferred: false,
+                    });
+                    balance_after = remaining_cents + fare_cents;
+                }
+                Event::Load { time, value_cents } => {
+                    if value_cents > balance_after {
+                        inconsistencies.push(BalanceInconsistency::LoadExceedsBalance {
+                            time,
+                            load_cents: value_cents,
+                            balance_after_cents: balance_after,
+                        });
+                    }
+                    steps.push(BalanceStep {
+                        time,
+                        kind: BalanceStepKind::Load,
+                        amount_cents: value_cents,
+                        balance_after_cents: balance_after,
+                        inferred: false,
+                    });
+                    balance_after = balance_after.saturating_sub(value_cents);
+                }
+            }
+        }
+
+        steps.reverse();
+        inconsistencies.reverse();
+        BalanceTimeline {
+            opening_balance_cents: balance_after,
+            steps,
+            inconsistencies,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::balance::{BalanceInconsistency, BalanceStepKind};
+    use crate::test_cards::{dump_datetime, helsinki, trip_dump, CardDump, HistoryDump};
+
+    /// A value ticket trip at the given hour on the given day of March 2020.
+    fn value_ticket(day: u32, hour: u64, fare_cents: u64, remaining_value: u64) -> HistoryDump {
+        trip_dump(
+            true,
+            dump_datetime(2020, 3, day, hour, 0),
+            fare_cents,
+            remaining_value,
+        )
+    }
+
+    #[test]
+    fn should_build_timeline_from_history_and_last_load() {
+        let mut dump = CardDump::new();
+        dump.stored_value(1000);
+        dump.last_load(dump_datetime(2020, 3, 2, 7, 0), 1000);
+        dump.push_history(value_ticket(2, 8, 280, 1000));
+        dump.push_history(value_ticket(1, 8, 280, 280));
+        let timeline = dump.build().balance_timeline();
+
+        assert_eq!(timeline.opening_balance_cents, 560);
+        assert!(timeline.inconsistencies.is_empty());
+        let kinds: Vec<_> = timeline.steps.iter().map(|s| s.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                BalanceStepKind::Debit,
+                BalanceStepKind::Load,
+                BalanceStepKind::Debit
+            ]
+        );
+        let balances: Vec<_> = timeline
+            .steps
+            .iter()
+            .map(|s| s.balance_after_cents)
+            .collect();
+        assert_eq!(balances, vec![280, 1280, 1000]);
+        assert!(timeline.steps.iter().all(|s| !s.inferred));
+    }
+
+    #[test]
+    fn should_infer_loads_from_gaps() {
+        let mut dump = CardDump::new();
+        dump.stored_value(4720);
+        dump.last_load(dump_datetime(2020, 3, 1, 7, 0), 500);
+        dump.push_history(value_ticket(1, 8, 280, 220));
+        dump.push_history(value_ticket(3, 8, 280, 4720));
+        let timeline = dump.build().balance_timeline();
+
+        let inferred = timeline.steps.iter().find(|s| s.inferred).unwrap();
+        assert_eq!(inferred.kind, BalanceStepKind::Load);
+        assert_eq!(inferred.amount_cents, 4780);
+        assert_eq!(inferred.time, helsinki(2020, 3, 1, 8, 0));
+        assert_eq!(
+            timeline.inconsistencies,
+            vec![BalanceInconsistency::LoadAfterLastLoad {
+                time: helsinki(2020, 3, 1, 8, 0)
+            }]
+        );
+    }
+
+    #[test]
+    fn should_report_unexplained_decreases() {
+        let mut dump = CardDump::new();
+        dump.stored_value(500);
+        dump.push_history(value_ticket(1, 8, 280, 720));
+        let timeline = dump.build().balance_timeline();
+
+        assert_eq!(timeline.opening_balance_cents, 1000);
+        assert_eq!(
+            timeline.inconsistencies,
+            vec![BalanceInconsistency::UnexplainedDecrease {
+                time: helsinki(2020, 3, 1, 8, 0),
+                missing_cents: 220,
+            }]
+        );
+    }
+}
@@ -0,0 +1,68 @@
+use chrono::prelude::*;
+use chrono::Duration;
+use std::cell::Cell;
+
+/// A source of the current time. Everything that depends on "now" takes a Clock,
+/// so that time-relative behavior can be tested without touching the host clock.
+pub trait Clock {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The host's real clock.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A clock that only moves when told to.
+#[derive(Debug, Clone)]
+pub struct FixedClock {
+    now: Cell<DateTime<Utc>>,
+}
+
+impl FixedClock {
+    pub fn new(now: DateTime<Utc>) -> FixedClock {
+        FixedClock {
+            now: Cell::new(now),
+        }
+    }
+
+    pub fn set(&self, now: DateTime<Utc>) {
+        self.now.set(now);
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.now.set(self.now.get() + by);
+    }
+}
+
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.now.get()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::clock::{Clock, FixedClock};
+    use chrono::prelude::*;
+    use chrono::Duration;
+
+    #[test]
+    fn fixed_clock_should_only_move_when_told_to() {
+        let start = Utc.with_ymd_and_hms(2020, 6, 15, 12, 0, 0).unwrap();
+        let clock = FixedClock::new(start);
+        assert_eq!(clock.now(), start);
+        assert_eq!(clock.now(), start);
+
+        clock.advance(Duration::minutes(90));
+        assert_eq!(clock.now(), start + Duration::minutes(90));
+
+        clock.set(start);
+        assert_eq!(clock.now(), start);
+    }
+}
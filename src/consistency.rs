@@ -0,0 +1,283 @@
+//! Sanity checks over a decoded card, for triaging cards whose data looks odd.
+
+use crate::balance::BalanceStepKind;
+use crate::clock::Clock;
+use crate::en1545date::{from_en1545_date_local, helsinki_date};
+use crate::travelcard::TravelCard;
+use chrono::prelude::*;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum WarningCode {
+    ValidityEndBeforeStart,
+    PeriodEndBeforeStart,
+    BalanceIncreaseWithoutLoad,
+    BoardingInFuture,
+    FareWithEmptyGroup,
+    DateBeforeIssuing,
+}
+
+/// Something on the card that doesn't look right. Cards with warnings still decode,
+/// but the affected values probably shouldn't be trusted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConsistencyWarning {
+    pub code: WarningCode,
+    /// The `TravelCard` field the warning is about, e.g. `period_pass.period_end_date_1`.
+    pub field: String,
+    pub explanation: String,
+}
+
+/// Dates that were never written are all zero, i.e. the En1545 zero date.
+fn is_unset(date: NaiveDate) -> bool {
+    date <= from_en1545_date_local(0)
+}
+
+struct Checker {
+    warnings: Vec<ConsistencyWarning>,
+}
+
+impl Checker {
+    fn warn(&mut self, code: WarningCode, field: &str, explanation: String) {
+        self.warnings.push(ConsistencyWarning {
+            code,
+            field: field.to_string(),
+            explanation,
+        });
+    }
+
+    fn check_boarding(&mut self, field: &str, boarded: DateTime<Utc>, now: DateTime<Utc>) {
+        if boarded > now {
+            self.warn(
+                WarningCode::BoardingInFuture,
+                field,
+                format!(
+                    "Boarded at {}, which is after the time of reading, {}.",
+                    boarded, now
+                ),
+            );
+        }
+    }
+
+    fn check_group(&mut self, field: &str, group_size: u8, fare_cents: u16) {
+        if group_size == 0 && fare_cents > 0 {
+            self.warn(
+                WarningCode::FareWithEmptyGroup,
+                field,
+                format!("A fare of {} cents was paid for a group of 0.", fare_cents),
+            );
+        }
+    }
+
+    fn check_issued(&mut self, field: &str, date: NaiveDate, issued: NaiveDate) {
+        if !is_unset(issued) && !is_unset(date) && date < issued {
+            self.warn(
+                WarningCode::DateBeforeIssuing,
+                field,
+                format!(
+                    "{} is before the card application was issued, on {}.",
+                    date, issued
+                ),
+            );
+        }
+    }
+}
+
+impl TravelCard {
+    /// Checks the card for values that contradict each other, using the current time
+    /// from the given clock as the time of reading.
+    pub fn check_consistency(&self, clock: &dyn Clock) -> Vec<ConsistencyWarning> {
+        self.check_consistency_at(clock.now())
+    }
+
+    /// Checks the card for values that contradict each other, as if it was read at `now`.
+    /// An empty list means nothing odd was found.
+    pub fn check_consistency_at(&self, now: DateTime<Utc>) -> Vec<ConsistencyWarning> {
+        let mut checker = Checker { warnings: vec![] };
+        let issued = helsinki_date(self.application_issuing_date);
+
+        let ticket = &self.e_ticket;
+        // An unset end is normal. The ticket's end is then worked out from its validity length.
+        let end_is_set = !is_unset(helsinki_date(ticket.validity_end_datetime));
+        if end_is_set && ticket.validity_end_datetime < ticket.validity_start_datetime {
+            checker.warn(
+                WarningCode::ValidityEndBeforeStart,
+                "e_ticket.validity_end_datetime",
+                format!(
+                    "The eTicket's validity ends at {}, before it starts at {}.",
+                    ticket.validity_end_datetime, ticket.validity_start_datetime
+                ),
+            );
+        }
+        checker.check_boarding("e_ticket.boarding_datetime", ticket.boarding_datetime, now);
+        checker.check_group(
+            "e_ticket.group_size",
+            ticket.group_size,
+            ticket.ticket_fare_cents,
+        );
+        checker.check_issued(
+            "e_ticket.sale_datetime",
+            helsinki_date(ticket.sale_datetime),
+            issued,
+        );
+
+        let pass = &self.period_pass;
+        let slot_fields = [
+            (
+                "period_pass.period_start_date_1",
+                "period_pass.period_end_date_1",
+            ),
+            (
+                "period_pass.period_start_date_2",
+                "period_pass.period_end_date_2",
+            ),
+        ];
+        for (slot, (start_field, end_field)) in pass.slots().iter().zip(slot_fields.iter()) {
+            if slot.end_date < slot.start_date {
+                checker.warn(
+                    WarningCode::PeriodEndBeforeStart,
+                    end_field,
+                    format!(
+                        "The period ends on {}, before it starts on {}.",
+                        slot.end_date, slot.start_date
+                    ),
+                );
+            }
+            checker.check_issued(start_field, slot.start_date, issued);
+        }
+        checker.check_boarding(
+            "period_pass.last_board_datetime",
+            pass.last_board_datetime,
+            now,
+        );
+        checker.check_issued(
+            "period_pass.loaded_period_datetime",
+            helsinki_date(pass.loaded_period_datetime),
+            issued,
+        );
+        checker.check_issued(
+            "last_load_datetime",
+            helsinki_date(self.last_load_datetime),
+            issued,
+        );
+
+        for (i, entry) in self.history.iter().enumerate() {
+            checker.check_boarding(
+                &format!("history[{}].boarding_datetime", i),
+                entry.boarding_datetime,
+                now,
+            );
+            checker.check_group(
+                &format!("history[{}].group_size", i),
+                entry.group_size,
+                entry.ticket_fare_cents,
+            );
+            checker.check_issued(
+                &format!("history[{}].boarding_datetime", i),
+                helsinki_date(entry.boarding_datetime),
+                issued,
+            );
+        }
+
+        for step in self.balance_timeline().steps {
+            if step.kind == BalanceStepKind::Load && step.inferred {
+                checker.warn(
+                    WarningCode::BalanceIncreaseWithoutLoad,
+                    "history",
+                    format!(
+                        "The balance rose by {} cents after {} with no matching load.",
+                        step.amount_cents, step.time
+                    ),
+                );
+            }
+        }
+
+        checker.warnings
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::consistency::WarningCode;
+    use crate::test_cards::{
+        dump_datetime, en1545_date, helsinki, trip_dump, CardDump, HistoryDump,
+    };
+
+    #[test]
+    fn clean_card_should_have_no_warnings() {
+        let mut dump = CardDump::new();
+        dump.issuing_date(en1545_date(2019, 1, 1));
+        dump.period_pass_1(0b000_001, en1545_date(2020, 3, 1), en1545_date(2020, 3, 30));
+        assert!(dump
+            .build()
+            .check_consistency_at(helsinki(2020, 3, 15, 12, 0))
+            .is_empty());
+    }
+
+    #[test]
+    fn unset_e_ticket_end_should_not_be_reported() {
+        let mut dump = CardDump::new();
+        dump.issuing_date(en1545_date(2019, 1, 1));
+        dump.e_ticket(
+            0b000_001,
+            dump_datetime(2020, 3, 2, 8, 0),
+            dump_datetime(1997, 1, 1, 0, 0),
+        );
+        dump.e_ticket_validity_length(0, 80); // 80 minutes
+        assert!(dump
+            .build()
+            .check_consistency_at(helsinki(2020, 3, 15, 12, 0))
+            .is_empty());
+    }
+
+    #[test]
+    fn should_report_each_anomaly() {
+        let mut dump = CardDump::new();
+        dump.issuing_date(en1545_date(2020, 1, 1));
+        dump.period_pass_1(0b000_001, en1545_date(2020, 3, 30), en1545_date(2020, 3, 1));
+        dump.e_ticket(
+            0b000_010,
+            dump_datetime(2020, 3, 2, 9, 20),
+            dump_datetime(2020, 3, 2, 8, 0),
+        );
+        dump.stored_value(5000);
+        dump.push_history(HistoryDump {
+            value_ticket: true,
+            boarding: dump_datetime(2019, 12, 1, 8, 0),
+            transfer_end: dump_datetime(2019, 12, 1, 9, 20),
+            fare_cents: 280,
+            group_size: 0,
+            remaining_value: 1000,
+        });
+        dump.push_history(trip_dump(true, dump_datetime(2020, 4, 1, 8, 0), 280, 5000));
+        let warnings = dump
+            .build()
+            .check_consistency_at(helsinki(2020, 3, 15, 12, 0));
+
+        let codes: Vec<_> = warnings
+            .iter()
+            .map(|w| (w.code, w.field.as_str()))
+            .collect();
+        assert_eq!(
+            codes,
+            vec![
+                (
+                    WarningCode::ValidityEndBeforeStart,
+                    "e_ticket.validity_end_datetime"
+                ),
+                (
+                    WarningCode::PeriodEndBeforeStart,
+                    "period_pass.period_end_date_1"
+                ),
+                (WarningCode::FareWithEmptyGroup, "history[0].group_size"),
+                (
+                    WarningCode::DateBeforeIssuing,
+                    "history[0].boarding_datetime"
+                ),
+                (
+                    WarningCode::BoardingInFuture,
+                    "history[1].boarding_datetime"
+                ),
+                (WarningCode::BalanceIncreaseWithoutLoad, "history"),
+            ]
+        );
+    }
+}
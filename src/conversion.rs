@@ -28,11 +28,7 @@ pub fn get_bits_as_u64(bytes: &[u8], bit_offset_index: usize, bit_length: usize)
     let num_relevant_bytes = (end_byte_index - byte_offset) + 1;
     let num_bits_to_mask = bit_offset_index % 8;
     let leading_and_value_bits = num_bits_to_mask + bit_length;
-    let num_bits_to_shift = if num_relevant_bytes * 8 > leading_and_value_bits {
-        (num_relevant_bytes * 8) - leading_and_value_bits
-    } else {
-        0
-    };
+    let num_bits_to_shift = (num_relevant_bytes * 8).saturating_sub(leading_and_value_bits);
     let mut and_mask = 0u64;
     for _ in 0..num_bits_to_mask {
         and_mask = (and_mask << 1) + 1;
@@ -57,9 +53,27 @@ pub fn get_bits_as_u64(bytes: &[u8], bit_offset_index: usize, bit_length: usize)
     ((welded_bytes & and_mask) >> num_bits_to_shift) >> ((8 - num_relevant_bytes) * 8)
 }
 
+/// Writes the lowest `bit_length` bits of `value` into `bytes`, starting at `bit_offset_index`.
+/// The inverse of `get_bits_as_u64`. Only used to build card dumps for tests.
+#[cfg(test)]
+pub(crate) fn set_bits(bytes: &mut [u8], bit_offset_index: usize, bit_length: usize, value: u64) {
+    for i in 0..bit_length {
+        let bit = (value >> (bit_length - 1 - i)) & 1;
+        let index = bit_offset_index + i;
+        let mask = 0b1000_0000 >> (index % 8);
+        if bit == 1 {
+            bytes[index / 8] |= mask;
+        } else {
+            bytes[index / 8] &= !mask;
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use crate::conversion::{get_bits_as_u16, get_bits_as_u32, get_bits_as_u64, get_bits_as_u8};
+    use crate::conversion::{
+        get_bits_as_u16, get_bits_as_u32, get_bits_as_u64, get_bits_as_u8, set_bits,
+    };
 
     #[test]
     fn to_bits_should_handle_trailing_and_leading_bits() {
@@ -117,4 +131,13 @@ mod test {
         let actual = get_bits_as_u16(&bytes, 15, 2);
         assert_eq!(expected, actual);
     }
+
+    #[test]
+    fn set_bits_should_round_trip_through_get_bits() {
+        let mut bytes = [0u8; 4];
+        set_bits(&mut bytes, 5, 17, 0b1_0110_0011_1010_0101);
+        assert_eq!(get_bits_as_u32(&bytes, 5, 17), 0b1_0110_0011_1010_0101);
+        assert_eq!(get_bits_as_u8(&bytes, 0, 5), 0);
+        assert_eq!(get_bits_as_u16(&bytes, 22, 10), 0);
+    }
 }
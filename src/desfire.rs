@@ -1,52 +1,59 @@
+pub mod applications;
+pub mod files;
+pub mod status;
+pub mod version;
+
+use applications::ApplicationId;
+use status::{split_response, DesfireStatus, StatusError};
+
+// --- HSL application files ---
+pub const APP_INFO_FILE_ID: u8 = 0x08;
+pub const CONTROL_INFO_FILE_ID: u8 = 0x00;
+pub const PERIOD_PASS_FILE_ID: u8 = 0x01;
+pub const STORED_VALUE_FILE_ID: u8 = 0x02;
+pub const E_TICKET_FILE_ID: u8 = 0x03;
+pub const HISTORY_FILE_ID: u8 = 0x04;
+
+/// The most data a single response frame holds. Longer responses are split into frames.
+pub(crate) const MAX_FRAME_DATA_LENGTH: usize = 59;
+
 // --- Commands ---
 ///DESFire GetVersion command.
-pub const GET_VERSION_COMMAND: [u8; 5] = [0x90, 0x60, 0x00, 0x00, 0x00];
+pub const GET_VERSION_COMMAND: [u8; 5] = Command::get_version();
 
 ///DESFire command to return all installed application IDs on the card.
-pub const GET_APPLICATION_IDS_COMMAND: [u8; 5] = [0x90, 0x6A, 0x00, 0x00, 0x00];
+pub const GET_APPLICATION_IDS_COMMAND: [u8; 5] = Command::get_application_ids();
 
 ///DESFire Select Application command for selecting the HSL application on the card.
 ///Returns OkResponse on success.
-pub const SELECT_HSL_COMMAND: [u8; 9] = [0x90, 0x5A, 0x00, 0x00, 0x03, 0x14, 0x20, 0xEF, 0x00];
+pub const SELECT_HSL_COMMAND: [u8; 9] = Command::select_application(ApplicationId::HSL);
 
 ///Command to read app info file, which contains application version, card name, etc.
-pub const READ_APP_INFO_COMMAND: [u8; 13] = [
-    0x90, 0xBD, 0x00, 0x00, 0x07, 0x08, 0x00, 0x00, 0x00, 0x0B, 0x00, 0x00, 0x00,
-];
+pub const READ_APP_INFO_COMMAND: [u8; 13] = Command::read_data(APP_INFO_FILE_ID, 0, 0x0B);
 
 ///Command to read the control info file from the card.
-pub const READ_CONTROL_INFO_COMMAND: [u8; 13] = [
-    0x90, 0xBD, 0x00, 0x00, 0x07, 0x00, 0x00, 0x00, 0x00, 0x0A, 0x00, 0x00, 0x00,
-];
+pub const READ_CONTROL_INFO_COMMAND: [u8; 13] = Command::read_data(CONTROL_INFO_FILE_ID, 0, 0x0A);
 
 ///Command to read the season pass file on the card.
-pub const READ_PERIOD_PASS_COMMAND: [u8; 13] = [
-    0x90, 0xBD, 0x00, 0x00, 0x07, 0x01, 0x00, 0x00, 0x00, 0x23, 0x00, 0x00, 0x00,
-];
+pub const READ_PERIOD_PASS_COMMAND: [u8; 13] = Command::read_data(PERIOD_PASS_FILE_ID, 0, 0x23);
 
 ///Command to read the stored value on the card.
-pub const READ_STORED_VALUE_COMMAND: [u8; 13] = [
-    0x90, 0xBD, 0x00, 0x00, 0x07, 0x02, 0x00, 0x00, 0x00, 0x0C, 0x00, 0x00, 0x00,
-];
+pub const READ_STORED_VALUE_COMMAND: [u8; 13] = Command::read_data(STORED_VALUE_FILE_ID, 0, 0x0C);
 
 ///Command to read the active eTicket on the card.
-pub const READ_E_TICKET_COMMAND: [u8; 13] = [
-    0x90, 0xBD, 0x00, 0x00, 0x07, 0x03, 0x00, 0x00, 0x00, 0x2D, 0x00, 0x00, 0x00,
-];
+pub const READ_E_TICKET_COMMAND: [u8; 13] = Command::read_data(E_TICKET_FILE_ID, 0, 0x2D);
 
 ///Command to read the 8 most recent transactions on the card.
-pub const READ_HISTORY_COMMAND: [u8; 13] = [
-    0x90, 0xBB, 0x00, 0x00, 0x07, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-];
+pub const READ_HISTORY_COMMAND: [u8; 13] = Command::read_records(HISTORY_FILE_ID, 0, 0);
 
 ///Reads the remaining bytes-to-be-sent if a read request returned a MoreData response.
-pub const READ_NEXT_COMMAND: [u8; 5] = [0x90, 0xAF, 0x00, 0x00, 0x00];
+pub const READ_NEXT_COMMAND: [u8; 5] = Command::additional_frame();
 
 // --- Responses ---
 ///DESFire OPERATION_OK response.
 pub const OK_RESPONSE: [u8; 2] = [0x91, 0x00];
 
-///DESFire error response. Not sure what it's known as internally.
+///DESFire PERMISSION_DENIED response. See `status::DesfireStatus` for every other status.
 pub const ERROR_RESPONSE: [u8; 2] = [0x91, 0x9D];
 
 ///DESFire ADDTIONAL_FRAME response. Indicates that there is more data, if the caller would like to ask for it.
@@ -86,22 +93,81 @@ pub enum Command {
     ReadNext,
 }
 
-/// Possible DESFire responses to APDU command from the HSL card.
-#[derive(Copy, Clone)]
-pub enum Response {
-    ///DESFire OPERATION_OK response.
-    Ok,
+/// DESFire native commands are sent wrapped in ISO 7816 APDUs:
+/// CLA 0x90, INS = the native command code, P1 = P2 = 0, then Lc, the data, and Le = 0.
+const fn wrap_native_read(instruction: u8, file_id: u8, offset: u32, length: u32) -> [u8; 13] {
+    [
+        0x90,
+        instruction,
+        0x00,
+        0x00,
+        0x07,
+        file_id,
+        offset as u8,
+        (offset >> 8) as u8,
+        (offset >> 16) as u8,
+        length as u8,
+        (length >> 8) as u8,
+        (length >> 16) as u8,
+        0x00,
+    ]
+}
+
+const fn wrap_native_empty(instruction: u8) -> [u8; 5] {
+    [0x90, instruction, 0x00, 0x00, 0x00]
+}
+
+/// Builders for DESFire native commands, each wrapped in an ISO 7816 APDU.
+impl Command {
+    pub const fn get_version() -> [u8; 5] {
+        wrap_native_empty(0x60)
+    }
+
+    pub const fn get_application_ids() -> [u8; 5] {
+        wrap_native_empty(0x6A)
+    }
+
+    pub const fn select_application(aid: ApplicationId) -> [u8; 9] {
+        [
+            0x90, 0x5A, 0x00, 0x00, 0x03, aid.0[0], aid.0[1], aid.0[2], 0x00,
+        ]
+    }
+
+    pub const fn get_file_ids() -> [u8; 5] {
+        wrap_native_empty(0x6F)
+    }
+
+    pub const fn get_file_settings(file_id: u8) -> [u8; 7] {
+        [0x90, 0xF5, 0x00, 0x00, 0x01, file_id, 0x00]
+    }
+
+    /// Reads `length` bytes from a data file, starting at `offset`. A length of 0 reads
+    /// to the end of the file. Offset and length are sent as 3 bytes, so only their lowest 24 bits are used.
+    pub const fn read_data(file_id: u8, offset: u32, length: u32) -> [u8; 13] {
+        wrap_native_read(0xBD, file_id, offset, length)
+    }
+
+    /// Reads `count` records from a record file, starting `offset` records back from
+    /// the newest one. A count of 0 reads every record. Only the lowest 24 bits of each are used.
+    pub const fn read_records(file_id: u8, offset: u32, count: u32) -> [u8; 13] {
+        wrap_native_read(0xBB, file_id, offset, count)
+    }
 
-    ///DESFire error response. Not sure what it's known as internally.
-    Error,
+    /// Asks for the next frame of a response that ended in MoreData.
+    pub const fn additional_frame() -> [u8; 5] {
+        wrap_native_empty(0xAF)
+    }
 
-    ///DESFire ADDTIONAL_FRAME response. Indicates that there is more data, if the caller would like to ask for it.
-    MoreData,
+    /// The ISO 7816 GET RESPONSE command, which asks for the `length` bytes a card announced
+    /// with a 0x61XX status word. Unlike the others, this isn't a wrapped native command.
+    pub const fn get_response(length: u8) -> [u8; 5] {
+        [0x00, 0xC0, 0x00, 0x00, length]
+    }
 }
 
-impl Into<&[u8]> for Command {
-    fn into(self) -> &'static [u8] {
-        match self {
+impl From<Command> for &[u8] {
+    fn from(val: Command) -> Self {
+        match val {
             Command::GetVersion => &GET_VERSION_COMMAND,
             Command::GetApplicationIds => &GET_APPLICATION_IDS_COMMAND,
             Command::SelectHsl => &SELECT_HSL_COMMAND,
@@ -116,24 +182,221 @@ impl Into<&[u8]> for Command {
     }
 }
 
-impl Into<&[u8]> for Response {
-    fn into(self) -> &'static [u8] {
+/// Something that can send an APDU to a card and return its response, status word included.
+/// This is all an NFC stack has to provide for the rest of the library to read cards through it.
+///
+/// Closures of the form `FnMut(&[u8]) -> Result<Vec<u8>, E>` implement it already.
+pub trait CardTransport {
+    type Error;
+
+    fn transceive(&mut self, command: &[u8]) -> Result<Vec<u8>, Self::Error>;
+}
+
+impl<F, E> CardTransport for F
+where
+    F: FnMut(&[u8]) -> Result<Vec<u8>, E>,
+{
+    type Error = E;
+
+    fn transceive(&mut self, command: &[u8]) -> Result<Vec<u8>, E> {
+        self(command)
+    }
+}
+
+#[derive(Debug)]
+pub enum TransceiveError<E> {
+    /// Sending a command to the card failed.
+    Transport(E),
+    /// The card answered with an error status.
+    Status(StatusError),
+    /// The response was too short to hold a status word.
+    ShortResponse(Vec<u8>),
+    /// The card kept saying it had more data after this many frames.
+    TooManyFrames(usize),
+}
+
+impl<E: std::fmt::Display> std::fmt::Display for TransceiveError<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
-            Response::Ok => &OK_RESPONSE,
-            Response::Error => &ERROR_RESPONSE,
-            Response::MoreData => &MORE_DATA_RESPONSE,
+            TransceiveError::Transport(e) => write!(f, "Failed to talk to the card: {}", e),
+            TransceiveError::Status(e) => write!(f, "{}", e),
+            TransceiveError::ShortResponse(r) => {
+                write!(f, "Response too short to hold a status word: {:X?}", r)
+            }
+            TransceiveError::TooManyFrames(n) => {
+                write!(f, "The card was still sending data after {} frames.", n)
+            }
         }
     }
 }
 
-impl std::cmp::PartialEq<Response> for [u8] {
-    fn eq(&self, other: &Response) -> bool {
-        self == Into::<&[u8]>::into(*other)
+impl<E: std::fmt::Debug + std::fmt::Display> std::error::Error for TransceiveError<E> {}
+
+/// Sends a command, and follows up with READ_NEXT for as long as the card responds
+/// with MoreData, up to `max_frames` frames in total. A 0x61XX status is followed up
+/// with an ISO GET RESPONSE instead. Returns the data of every frame
+/// joined together, without status words.
+///
+/// Works for any command whose response can span frames, e.g. GetVersion, which always
+/// takes three, or reads longer than a single frame.
+pub fn transceive_chained<T: CardTransport + ?Sized>(
+    transport: &mut T,
+    command: &[u8],
+    max_frames: usize,
+) -> Result<Vec<u8>, TransceiveError<T::Error>> {
+    let mut data = vec![];
+    let mut next_command;
+    let mut command = command;
+    for _ in 0..max_frames {
+        let response = transport
+            .transceive(command)
+            .map_err(TransceiveError::Transport)?;
+        let (frame, status) = match split_response(&response) {
+            Some(split) => split,
+            None => return Err(TransceiveError::ShortResponse(response)),
+        };
+        status.check().map_err(TransceiveError::Status)?;
+        data.extend_from_slice(frame);
+        if !status.is_more_data() {
+            return Ok(data);
+        }
+        next_command = match status {
+            DesfireStatus::BytesRemaining(length) => Command::get_response(length),
+            _ => READ_NEXT_COMMAND,
+        };
+        command = &next_command;
     }
+    Err(TransceiveError::TooManyFrames(max_frames))
 }
 
-impl std::cmp::PartialEq<Response> for &[u8] {
-    fn eq(&self, other: &Response) -> bool {
-        *self == Into::<&[u8]>::into(*other)
+#[cfg(test)]
+mod test {
+    use crate::desfire::*;
+
+    // The command bytes as they were written out by hand before the builders existed.
+    #[test]
+    fn builders_should_match_original_commands() {
+        assert_eq!(GET_VERSION_COMMAND, [0x90, 0x60, 0x00, 0x00, 0x00]);
+        assert_eq!(GET_APPLICATION_IDS_COMMAND, [0x90, 0x6A, 0x00, 0x00, 0x00]);
+        assert_eq!(
+            SELECT_HSL_COMMAND,
+            [0x90, 0x5A, 0x00, 0x00, 0x03, 0x14, 0x20, 0xEF, 0x00]
+        );
+        assert_eq!(
+            READ_APP_INFO_COMMAND,
+            [0x90, 0xBD, 0x00, 0x00, 0x07, 0x08, 0x00, 0x00, 0x00, 0x0B, 0x00, 0x00, 0x00]
+        );
+        assert_eq!(
+            READ_CONTROL_INFO_COMMAND,
+            [0x90, 0xBD, 0x00, 0x00, 0x07, 0x00, 0x00, 0x00, 0x00, 0x0A, 0x00, 0x00, 0x00]
+        );
+        assert_eq!(
+            READ_PERIOD_PASS_COMMAND,
+            [0x90, 0xBD, 0x00, 0x00, 0x07, 0x01, 0x00, 0x00, 0x00, 0x23, 0x00, 0x00, 0x00]
+        );
+        assert_eq!(
+            READ_STORED_VALUE_COMMAND,
+            [0x90, 0xBD, 0x00, 0x00, 0x07, 0x02, 0x00, 0x00, 0x00, 0x0C, 0x00, 0x00, 0x00]
+        );
+        assert_eq!(
+            READ_E_TICKET_COMMAND,
+            [0x90, 0xBD, 0x00, 0x00, 0x07, 0x03, 0x00, 0x00, 0x00, 0x2D, 0x00, 0x00, 0x00]
+        );
+        assert_eq!(
+            READ_HISTORY_COMMAND,
+            [0x90, 0xBB, 0x00, 0x00, 0x07, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]
+        );
+        assert_eq!(READ_NEXT_COMMAND, [0x90, 0xAF, 0x00, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn should_encode_offsets_and_lengths_little_endian() {
+        assert_eq!(
+            Command::read_data(0x05, 0x012345, 0x0200),
+            [0x90, 0xBD, 0x00, 0x00, 0x07, 0x05, 0x45, 0x23, 0x01, 0x00, 0x02, 0x00, 0x00]
+        );
+        assert_eq!(
+            Command::get_file_settings(0x04),
+            [0x90, 0xF5, 0x00, 0x00, 0x01, 0x04, 0x00]
+        );
+        assert_eq!(Command::get_file_ids(), [0x90, 0x6F, 0x00, 0x00, 0x00]);
+    }
+
+    /// Plays back the given responses in order, recording the commands it was sent.
+    struct Playback {
+        responses: Vec<&'static [u8]>,
+        sent: Vec<Vec<u8>>,
+    }
+
+    impl Playback {
+        fn new(responses: &[&'static [u8]]) -> Playback {
+            Playback {
+                responses: responses.to_vec(),
+                sent: vec![],
+            }
+        }
+    }
+
+    impl CardTransport for Playback {
+        type Error = ();
+
+        fn transceive(&mut self, command: &[u8]) -> Result<Vec<u8>, ()> {
+            self.sent.push(command.to_vec());
+            self.responses
+                .get(self.sent.len() - 1)
+                .map(|r| r.to_vec())
+                .ok_or(())
+        }
+    }
+
+    #[test]
+    fn should_join_chained_frames() {
+        let mut transport = Playback::new(&[
+            &[0x01, 0x02, 0x91, 0xAF],
+            &[0x03, 0x91, 0xAF],
+            &[0x04, 0x91, 0x00],
+        ]);
+        let data = transceive_chained(&mut transport, &READ_HISTORY_COMMAND, 3).unwrap();
+        assert_eq!(data, vec![0x01, 0x02, 0x03, 0x04]);
+        assert_eq!(
+            transport.sent,
+            vec![
+                READ_HISTORY_COMMAND.to_vec(),
+                READ_NEXT_COMMAND.to_vec(),
+                READ_NEXT_COMMAND.to_vec()
+            ]
+        );
+    }
+
+    #[test]
+    fn should_follow_iso_bytes_remaining_with_get_response() {
+        let mut transport = Playback::new(&[&[0x01, 0x61, 0x02], &[0x02, 0x03, 0x90, 0x00]]);
+        let data = transceive_chained(&mut transport, &GET_VERSION_COMMAND, 2).unwrap();
+        assert_eq!(data, vec![0x01, 0x02, 0x03]);
+        assert_eq!(transport.sent[1], vec![0x00, 0xC0, 0x00, 0x00, 0x02]);
+    }
+
+    #[test]
+    fn should_stop_at_max_frames() {
+        let mut transport = Playback::new(&[&[0x01, 0x91, 0xAF], &[0x02, 0x91, 0xAF]]);
+        let result = transceive_chained(&mut transport, &GET_VERSION_COMMAND, 2);
+        assert!(matches!(result, Err(TransceiveError::TooManyFrames(2))));
+        assert_eq!(transport.sent.len(), 2);
+    }
+
+    #[test]
+    fn should_fail_on_error_in_later_frame() {
+        let mut transport = Playback::new(&[&[0x01, 0x91, 0xAF], &[0x91, 0xCA]]);
+        let result = transceive_chained(&mut transport, &READ_HISTORY_COMMAND, 3);
+        assert!(matches!(
+            result,
+            Err(TransceiveError::Status(StatusError(
+                status::DesfireStatus::CommandAborted
+            )))
+        ));
+
+        let mut transport = Playback::new(&[&[0x90]]);
+        let result = transceive_chained(&mut transport, &READ_HISTORY_COMMAND, 3);
+        assert!(matches!(result, Err(TransceiveError::ShortResponse(_))));
     }
 }
@@ -0,0 +1,145 @@
+//! Decoding the response to the DESFire GetApplicationIDs command, and recognizing
+//! the applications on a card.
+
+use crate::desfire::{
+    transceive_chained, CardTransport, TransceiveError, GET_APPLICATION_IDS_COMMAND,
+};
+use std::fmt;
+
+/// A DESFire application ID. Stored as the three bytes sent to and received from the card,
+/// which the DESFire spec treats as a little-endian number.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct ApplicationId(pub [u8; 3]);
+
+impl ApplicationId {
+    /// The current HSL travel card application, as selected by `SELECT_HSL_COMMAND`.
+    pub const HSL: ApplicationId = ApplicationId([0x14, 0x20, 0xEF]);
+    /// The application used by older HSL cards. This library can't read it.
+    pub const HSL_LEGACY: ApplicationId = ApplicationId([0x11, 0x20, 0xEF]);
+
+    /// The ID as a number, decoded little-endian as the DESFire spec does.
+    pub fn value(self) -> u32 {
+        u32::from_le_bytes([self.0[0], self.0[1], self.0[2], 0])
+    }
+
+    pub fn known_application(self) -> Option<KnownApplication> {
+        match self {
+            ApplicationId::HSL => Some(KnownApplication::Hsl),
+            ApplicationId::HSL_LEGACY => Some(KnownApplication::HslLegacy),
+            _ => None,
+        }
+    }
+}
+
+/// Formats the ID as its bytes in card order, e.g. `1420EF` for HSL, which is how
+/// transit AIDs are usually written.
+impl fmt::Display for ApplicationId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:02X}{:02X}{:02X}", self.0[0], self.0[1], self.0[2])
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum KnownApplication {
+    Hsl,
+    HslLegacy,
+}
+
+/// What a card's applications say about whether it can be read as an HSL travel card.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CardIdentification {
+    /// The card has the HSL application, and can be read.
+    Hsl,
+    /// The card doesn't have the HSL application. Lists the other transit applications
+    /// that were recognized, if any.
+    NotHsl { known: Vec<KnownApplication> },
+}
+
+impl CardIdentification {
+    pub fn from_application_ids(ids: &[ApplicationId]) -> CardIdentification {
+        if ids.contains(&ApplicationId::HSL) {
+            CardIdentification::Hsl
+        } else {
+            CardIdentification::NotHsl {
+                known: ids.iter().filter_map(|id| id.known_application()).collect(),
+            }
+        }
+    }
+
+    pub fn is_hsl(&self) -> bool {
+        *self == CardIdentification::Hsl
+    }
+}
+
+impl fmt::Display for CardIdentification {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CardIdentification::Hsl => write!(f, "HSL card"),
+            CardIdentification::NotHsl { known } if known.is_empty() => {
+                write!(f, "Not an HSL card")
+            }
+            CardIdentification::NotHsl { known } => {
+                write!(f, "Not an HSL card. Found applications: {:?}", known)
+            }
+        }
+    }
+}
+
+/// Parses the data of a GetApplicationIDs response, without its status word.
+/// A trailing partial ID is ignored.
+pub fn parse_application_ids(bytes: &[u8]) -> Vec<ApplicationId> {
+    bytes
+        .chunks_exact(3)
+        .map(|chunk| ApplicationId([chunk[0], chunk[1], chunk[2]]))
+        .collect()
+}
+
+/// Asks the card for its application IDs. Cards with many applications send them in two frames.
+pub fn read_application_ids<T: CardTransport + ?Sized>(
+    transport: &mut T,
+) -> Result<Vec<ApplicationId>, TransceiveError<T::Error>> {
+    let data = transceive_chained(transport, &GET_APPLICATION_IDS_COMMAND, 2)?;
+    Ok(parse_application_ids(&data))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::desfire::applications::{
+        parse_application_ids, read_application_ids, ApplicationId, CardIdentification,
+        KnownApplication,
+    };
+    use crate::desfire::SELECT_HSL_COMMAND;
+
+    #[test]
+    fn hsl_id_should_match_select_command() {
+        assert_eq!(ApplicationId::HSL.0, SELECT_HSL_COMMAND[5..8]);
+        assert_eq!(ApplicationId::HSL.value(), 0xEF2014);
+        assert_eq!(ApplicationId::HSL.to_string(), "1420EF");
+    }
+
+    #[test]
+    fn should_identify_hsl_cards() {
+        let ids = read_application_ids(&mut |_: &[u8]| {
+            Ok::<_, ()>(vec![0x11, 0x20, 0xEF, 0x14, 0x20, 0xEF, 0x91, 0x00])
+        })
+        .unwrap();
+        assert_eq!(ids, vec![ApplicationId::HSL_LEGACY, ApplicationId::HSL]);
+        assert!(CardIdentification::from_application_ids(&ids).is_hsl());
+    }
+
+    #[test]
+    fn should_identify_other_cards() {
+        let ids = parse_application_ids(&[0x11, 0x20, 0xEF, 0x12, 0x34, 0x56]);
+        let identification = CardIdentification::from_application_ids(&ids);
+        assert_eq!(
+            identification,
+            CardIdentification::NotHsl {
+                known: vec![KnownApplication::HslLegacy]
+            }
+        );
+        assert!(!identification.is_hsl());
+
+        let empty = CardIdentification::from_application_ids(&[]);
+        assert_eq!(empty.to_string(), "Not an HSL card");
+    }
+}
@@ -0,0 +1,373 @@
+//! Discovering the files in the selected application with GetFileIDs and GetFileSettings,
+//! and reading them based on what the card says about them.
+
+use crate::desfire::{
+    transceive_chained, CardTransport, Command, TransceiveError, MAX_FRAME_DATA_LENGTH,
+};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum FileType {
+    StandardData,
+    BackupData,
+    Value,
+    LinearRecord,
+    CyclicRecord,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum CommunicationMode {
+    Plain,
+    /// Plain data, followed by a MAC.
+    Maced,
+    Enciphered,
+}
+
+impl From<u8> for CommunicationMode {
+    fn from(value: u8) -> Self {
+        match value & 0b11 {
+            0b01 => CommunicationMode::Maced,
+            0b11 => CommunicationMode::Enciphered,
+            // 0b10 is also plain, according to the DESFire spec.
+            _ => CommunicationMode::Plain,
+        }
+    }
+}
+
+/// Who may perform an operation on a file.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum AccessKey {
+    /// Anyone authenticated with the given application key.
+    Key(u8),
+    /// Anyone, without authenticating.
+    Free,
+    Denied,
+}
+
+impl From<u8> for AccessKey {
+    fn from(nibble: u8) -> Self {
+        match nibble & 0x0F {
+            0x0E => AccessKey::Free,
+            0x0F => AccessKey::Denied,
+            key => AccessKey::Key(key),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct AccessRights {
+    pub read: AccessKey,
+    pub write: AccessKey,
+    pub read_write: AccessKey,
+    pub change: AccessKey,
+}
+
+impl From<u16> for AccessRights {
+    fn from(value: u16) -> Self {
+        AccessRights {
+            read: AccessKey::from((value >> 12) as u8),
+            write: AccessKey::from((value >> 8) as u8),
+            read_write: AccessKey::from((value >> 4) as u8),
+            change: AccessKey::from(value as u8),
+        }
+    }
+}
+
+impl AccessRights {
+    /// True if the file can be read without authenticating.
+    pub fn is_freely_readable(&self) -> bool {
+        self.read == AccessKey::Free || self.read_write == AccessKey::Free
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FileSize {
+    /// Standard and backup data files.
+    Data { size: u32 },
+    Value {
+        lower_limit: i32,
+        upper_limit: i32,
+        limited_credit_value: i32,
+        limited_credit_enabled: bool,
+    },
+    /// Linear and cyclic record files.
+    Record {
+        record_size: u32,
+        max_records: u32,
+        current_records: u32,
+    },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FileSettings {
+    pub file_type: FileType,
+    pub communication_mode: CommunicationMode,
+    pub access_rights: AccessRights,
+    pub size: FileSize,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FileSettingsError {
+    UnknownFileType(u8),
+    /// The response was too short for its file type.
+    Length(usize),
+}
+
+impl std::fmt::Display for FileSettingsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            FileSettingsError::UnknownFileType(t) => write!(f, "Unknown file type {:#04X}.", t),
+            FileSettingsError::Length(l) => {
+                write!(f, "GetFileSettings response is too short: {} bytes.", l)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FileSettingsError {}
+
+fn read_u24(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0])
+}
+
+fn read_i32(bytes: &[u8]) -> i32 {
+    i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+impl FileSettings {
+    /// Parses the data of a GetFileSettings response, without its status word.
+    pub fn parse(bytes: &[u8]) -> Result<FileSettings, FileSettingsError> {
+        if bytes.is_empty() {
+            return Err(FileSettingsError::Length(0));
+        }
+        let (file_type, expected_length) = match bytes[0] {
+            0x00 => (FileType::StandardData, 7),
+            0x01 => (FileType::BackupData, 7),
+            0x02 => (FileType::Value, 17),
+            0x03 => (FileType::LinearRecord, 13),
+            0x04 => (FileType::CyclicRecord, 13),
+            other => return Err(FileSettingsError::UnknownFileType(other)),
+        };
+        if bytes.len() < expected_length {
+            return Err(FileSettingsError::Length(bytes.len()));
+        }
+
+        let size = match file_type {
+            FileType::StandardData | FileType::BackupData => FileSize::Data {
+                size: read_u24(&bytes[4..7]),
+            },
+            FileType::Value => FileSize::Value {
+                lower_limit: read_i32(&bytes[4..8]),
+                upper_limit: read_i32(&bytes[8..12]),
+                limited_credit_value: read_i32(&bytes[12..16]),
+                limited_credit_enabled: bytes[16] & 0x01 != 0,
+            },
+            FileType::LinearRecord | FileType::CyclicRecord => FileSize::Record {
+                record_size: read_u24(&bytes[4..7]),
+                max_records: read_u24(&bytes[7..10]),
+                current_records: read_u24(&bytes[10..13]),
+            },
+        };
+
+        Ok(FileSettings {
+            file_type,
+            communication_mode: CommunicationMode::from(bytes[1]),
+            access_rights: AccessRights::from(u16::from_le_bytes([bytes[2], bytes[3]])),
+            size,
+        })
+    }
+
+    /// How many bytes reading the whole file returns. None for value files, which aren't read
+    /// as bytes, and for record files too large to measure in a u32.
+    pub fn read_length(&self) -> Option<u32> {
+        match self.size {
+            FileSize::Data { size } => Some(size),
+            FileSize::Record {
+                record_size,
+                current_records,
+                ..
+            } => record_size.checked_mul(current_records),
+            FileSize::Value { .. } => None,
+        }
+    }
+
+    /// The command that reads the whole file with the given ID. None for value files.
+    pub fn read_command(&self, file_id: u8) -> Option<[u8; 13]> {
+        match self.size {
+            FileSize::Data { size } => Some(Command::read_data(file_id, 0, size)),
+            FileSize::Record { .. } => Some(Command::read_records(file_id, 0, 0)),
+            FileSize::Value { .. } => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum FileError<E> {
+    Transceive(TransceiveError<E>),
+    Settings(FileSettingsError),
+    /// Value files hold a number, and can't be read as bytes.
+    NotReadable(FileType),
+}
+
+impl<E: std::fmt::Display> std::fmt::Display for FileError<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            FileError::Transceive(e) => write!(f, "{}", e),
+            FileError::Settings(e) => write!(f, "{}", e),
+            FileError::NotReadable(t) => write!(f, "{:?} files can't be read as bytes.", t),
+        }
+    }
+}
+
+impl<E: std::fmt::Debug + std::fmt::Display> std::error::Error for FileError<E> {}
+
+impl<E> From<TransceiveError<E>> for FileError<E> {
+    fn from(e: TransceiveError<E>) -> Self {
+        FileError::Transceive(e)
+    }
+}
+
+impl<E> From<FileSettingsError> for FileError<E> {
+    fn from(e: FileSettingsError) -> Self {
+        FileError::Settings(e)
+    }
+}
+
+/// Asks the card for the IDs of the files in the selected application.
+pub fn read_file_ids<T: CardTransport + ?Sized>(
+    transport: &mut T,
+) -> Result<Vec<u8>, TransceiveError<T::Error>> {
+    transceive_chained(transport, &Command::get_file_ids(), 1)
+}
+
+/// Asks the card for the settings of a file in the selected application.
+pub fn read_file_settings<T: CardTransport + ?Sized>(
+    transport: &mut T,
+    file_id: u8,
+) -> Result<FileSettings, FileError<T::Error>> {
+    let data = transceive_chained(transport, &Command::get_file_settings(file_id), 1)?;
+    Ok(FileSettings::parse(&data)?)
+}
+
+/// Reads a whole file from the selected application, sizing the read by asking the card
+/// for the file's settings first.
+pub fn read_file<T: CardTransport + ?Sized>(
+    transport: &mut T,
+    file_id: u8,
+) -> Result<Vec<u8>, FileError<T::Error>> {
+    let settings = read_file_settings(transport, file_id)?;
+    let command = settings
+        .read_command(file_id)
+        .ok_or(FileError::NotReadable(settings.file_type))?;
+    // Reading a record file with no records fails with a boundary error, so don't.
+    if let FileSize::Record {
+        current_records: 0, ..
+    } = settings.size
+    {
+        return Ok(vec![]);
+    }
+    let length = settings.read_length().unwrap_or(u32::MAX);
+    let max_frames = (length / MAX_FRAME_DATA_LENGTH as u32 + 1) as usize;
+    Ok(transceive_chained(transport, &command, max_frames)?)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::desfire::files::{
+        read_file, AccessKey, CommunicationMode, FileError, FileSettings, FileSize, FileType,
+    };
+    use crate::desfire::READ_PERIOD_PASS_COMMAND;
+
+    #[test]
+    fn should_parse_data_file_settings() {
+        // The HSL period pass file: plain, freely readable, 0x23 bytes long.
+        let settings = FileSettings::parse(&[0x00, 0x00, 0x12, 0xE1, 0x23, 0x00, 0x00]).unwrap();
+        assert_eq!(settings.file_type, FileType::StandardData);
+        assert_eq!(settings.communication_mode, CommunicationMode::Plain);
+        assert_eq!(settings.access_rights.read, AccessKey::Free);
+        assert_eq!(settings.access_rights.write, AccessKey::Key(1));
+        assert_eq!(settings.access_rights.change, AccessKey::Key(2));
+        assert!(settings.access_rights.is_freely_readable());
+        assert_eq!(settings.size, FileSize::Data { size: 0x23 });
+        assert_eq!(settings.read_command(0x01), Some(READ_PERIOD_PASS_COMMAND));
+    }
+
+    #[test]
+    fn should_parse_record_file_settings() {
+        let settings = FileSettings::parse(&[
+            0x04, 0x03, 0x11, 0xEF, 0x0C, 0x00, 0x00, 0x10, 0x00, 0x00, 0x08, 0x00, 0x00,
+        ])
+        .unwrap();
+        assert_eq!(settings.file_type, FileType::CyclicRecord);
+        assert_eq!(settings.communication_mode, CommunicationMode::Enciphered);
+        assert_eq!(settings.access_rights.read_write, AccessKey::Key(1));
+        assert_eq!(settings.access_rights.read, AccessKey::Free);
+        assert_eq!(settings.access_rights.write, AccessKey::Denied);
+        assert_eq!(settings.read_length(), Some(96));
+
+        let huge = FileSettings::parse(&[
+            0x03, 0x00, 0xEE, 0xEE, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+        ])
+        .unwrap();
+        assert_eq!(huge.read_length(), None);
+
+        assert!(FileSettings::parse(&[0x04, 0x00, 0x00]).is_err());
+        assert!(FileSettings::parse(&[0x09]).is_err());
+    }
+
+    #[test]
+    fn should_size_reads_from_file_settings() {
+        let mut sent = vec![];
+        let data = read_file(
+            &mut |command: &[u8]| {
+                sent.push(command.to_vec());
+                Ok::<_, ()>(match command[1] {
+                    0xF5 => vec![0x00, 0x00, 0x00, 0xE0, 0x40, 0x00, 0x00, 0x91, 0x00],
+                    0xBD => {
+                        let mut frame = vec![0xAA; 59];
+                        frame.extend_from_slice(&[0x91, 0xAF]);
+                        frame
+                    }
+                    _ => vec![0xBB, 0xBB, 0xBB, 0xBB, 0xBB, 0x91, 0x00],
+                })
+            },
+            0x05,
+        )
+        .unwrap();
+
+        assert_eq!(data.len(), 64);
+        assert_eq!(sent[1][9], 0x40);
+        assert_eq!(sent.len(), 3);
+
+        let value_file = read_file(
+            &mut |_: &[u8]| {
+                Ok::<_, ()>(vec![
+                    0x02, 0x00, 0x00, 0x00, 0, 0, 0, 0, 0xFF, 0, 0, 0, 0, 0, 0, 0, 0, 0x91, 0x00,
+                ])
+            },
+            0x06,
+        );
+        assert!(matches!(
+            value_file,
+            Err(FileError::NotReadable(FileType::Value))
+        ));
+    }
+
+    #[test]
+    fn should_not_read_empty_record_files() {
+        let mut sent = vec![];
+        let data = read_file(
+            &mut |command: &[u8]| {
+                sent.push(command[1]);
+                Ok::<_, ()>(vec![
+                    0x04, 0x00, 0xEE, 0xEE, 0x0C, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00,
+                    0x91, 0x00,
+                ])
+            },
+            0x04,
+        )
+        .unwrap();
+
+        assert!(data.is_empty());
+        assert_eq!(sent, vec![0xF5]);
+    }
+}
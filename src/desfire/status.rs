@@ -0,0 +1,281 @@
+//! DESFire and ISO 7816 status words.
+
+use std::fmt;
+
+/// The status word at the end of every response from the card. Native DESFire commands
+/// answer with SW1 = 0x91, and ISO 7816 commands with the usual ISO status words.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum DesfireStatus {
+    // --- Native DESFire (SW1 = 0x91) ---
+    OperationOk,
+    NoChanges,
+    OutOfEepromError,
+    IllegalCommandCode,
+    IntegrityError,
+    NoSuchKey,
+    LengthError,
+    PermissionDenied,
+    ParameterError,
+    ApplicationNotFound,
+    ApplicationIntegrityError,
+    AuthenticationError,
+    AdditionalFrame,
+    BoundaryError,
+    PiccIntegrityError,
+    CommandAborted,
+    PiccDisabledError,
+    CountError,
+    DuplicateError,
+    EepromError,
+    FileNotFound,
+    FileIntegrityError,
+
+    // --- ISO 7816 ---
+    Success,
+    /// SW1 = 0x61. SW2 bytes are still available.
+    BytesRemaining(u8),
+    MemoryFailure,
+    WrongLength,
+    SecurityStatusNotSatisfied,
+    ConditionsNotSatisfied,
+    IncorrectData,
+    FileOrApplicationNotFound,
+    RecordNotFound,
+    IncorrectParameters,
+    /// SW1 = 0x6C. The command should be re-sent with Le set to SW2.
+    WrongLe(u8),
+    InstructionNotSupported,
+    ClassNotSupported,
+    NoPreciseDiagnosis,
+
+    Unknown(u8, u8),
+}
+
+impl DesfireStatus {
+    pub fn from_sw(sw1: u8, sw2: u8) -> DesfireStatus {
+        use DesfireStatus::*;
+        match (sw1, sw2) {
+            (0x91, 0x00) => OperationOk,
+            (0x91, 0x0C) => NoChanges,
+            (0x91, 0x0E) => OutOfEepromError,
+            (0x91, 0x1C) => IllegalCommandCode,
+            (0x91, 0x1E) => IntegrityError,
+            (0x91, 0x40) => NoSuchKey,
+            (0x91, 0x7E) => LengthError,
+            (0x91, 0x9D) => PermissionDenied,
+            (0x91, 0x9E) => ParameterError,
+            (0x91, 0xA0) => ApplicationNotFound,
+            (0x91, 0xA1) => ApplicationIntegrityError,
+            (0x91, 0xAE) => AuthenticationError,
+            (0x91, 0xAF) => AdditionalFrame,
+            (0x91, 0xBE) => BoundaryError,
+            (0x91, 0xC1) => PiccIntegrityError,
+            (0x91, 0xCA) => CommandAborted,
+            (0x91, 0xCD) => PiccDisabledError,
+            (0x91, 0xCE) => CountError,
+            (0x91, 0xDE) => DuplicateError,
+            (0x91, 0xEE) => EepromError,
+            (0x91, 0xF0) => FileNotFound,
+            (0x91, 0xF1) => FileIntegrityError,
+            (0x90, 0x00) => Success,
+            (0x61, remaining) => BytesRemaining(remaining),
+            (0x65, 0x81) => MemoryFailure,
+            (0x67, 0x00) => WrongLength,
+            (0x69, 0x82) => SecurityStatusNotSatisfied,
+            (0x69, 0x85) => ConditionsNotSatisfied,
+            (0x6A, 0x80) => IncorrectData,
+            (0x6A, 0x82) => FileOrApplicationNotFound,
+            (0x6A, 0x83) => RecordNotFound,
+            (0x6A, 0x86) => IncorrectParameters,
+            (0x6C, le) => WrongLe(le),
+            (0x6D, 0x00) => InstructionNotSupported,
+            (0x6E, 0x00) => ClassNotSupported,
+            (0x6F, 0x00) => NoPreciseDiagnosis,
+            (sw1, sw2) => Unknown(sw1, sw2),
+        }
+    }
+
+    pub fn sw(self) -> [u8; 2] {
+        use DesfireStatus::*;
+        match self {
+            OperationOk => [0x91, 0x00],
+            NoChanges => [0x91, 0x0C],
+            OutOfEepromError => [0x91, 0x0E],
+            IllegalCommandCode => [0x91, 0x1C],
+            IntegrityError => [0x91, 0x1E],
+            NoSuchKey => [0x91, 0x40],
+            LengthError => [0x91, 0x7E],
+            PermissionDenied => [0x91, 0x9D],
+            ParameterError => [0x91, 0x9E],
+            ApplicationNotFound => [0x91, 0xA0],
+            ApplicationIntegrityError => [0x91, 0xA1],
+            AuthenticationError => [0x91, 0xAE],
+            AdditionalFrame => [0x91, 0xAF],
+            BoundaryError => [0x91, 0xBE],
+            PiccIntegrityError => [0x91, 0xC1],
+            CommandAborted => [0x91, 0xCA],
+            PiccDisabledError => [0x91, 0xCD],
+            CountError => [0x91, 0xCE],
+            DuplicateError => [0x91, 0xDE],
+            EepromError => [0x91, 0xEE],
+            FileNotFound => [0x91, 0xF0],
+            FileIntegrityError => [0x91, 0xF1],
+            Success => [0x90, 0x00],
+            BytesRemaining(remaining) => [0x61, remaining],
+            MemoryFailure => [0x65, 0x81],
+            WrongLength => [0x67, 0x00],
+            SecurityStatusNotSatisfied => [0x69, 0x82],
+            ConditionsNotSatisfied => [0x69, 0x85],
+            IncorrectData => [0x6A, 0x80],
+            FileOrApplicationNotFound => [0x6A, 0x82],
+            RecordNotFound => [0x6A, 0x83],
+            IncorrectParameters => [0x6A, 0x86],
+            WrongLe(le) => [0x6C, le],
+            InstructionNotSupported => [0x6D, 0x00],
+            ClassNotSupported => [0x6E, 0x00],
+            NoPreciseDiagnosis => [0x6F, 0x00],
+            Unknown(sw1, sw2) => [sw1, sw2],
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        use DesfireStatus::*;
+        match self {
+            OperationOk => "Successful operation.",
+            NoChanges => "No changes done to backup files.",
+            OutOfEepromError => "Insufficient NV-memory to complete the command.",
+            IllegalCommandCode => "Command code not supported.",
+            IntegrityError => "CRC or MAC does not match the data.",
+            NoSuchKey => "Invalid key number specified.",
+            LengthError => "Length of command string invalid.",
+            PermissionDenied => "Current configuration or status does not allow the command.",
+            ParameterError => "Value of the parameter(s) invalid.",
+            ApplicationNotFound => "Requested application not present on the card.",
+            ApplicationIntegrityError => "Unrecoverable error within the application.",
+            AuthenticationError => "Current authentication status does not allow the command.",
+            AdditionalFrame => "Additional data frame is expected to be sent.",
+            BoundaryError => "Attempt to read or write beyond the file's limits.",
+            PiccIntegrityError => "Unrecoverable error within the card.",
+            CommandAborted => "Previous command was not fully completed.",
+            PiccDisabledError => "The card was disabled by an unrecoverable error.",
+            CountError => "The card already has the maximum number of applications.",
+            DuplicateError => "An application or file with that number already exists.",
+            EepromError => "Could not complete NV-write operation.",
+            FileNotFound => "Specified file number does not exist.",
+            FileIntegrityError => "Unrecoverable error within the file.",
+            Success => "Command completed successfully.",
+            BytesRemaining(_) => "More response bytes are available.",
+            MemoryFailure => "Memory failure.",
+            WrongLength => "Wrong length.",
+            SecurityStatusNotSatisfied => "Security status not satisfied.",
+            ConditionsNotSatisfied => "Conditions of use not satisfied.",
+            IncorrectData => "Incorrect parameters in the command data field.",
+            FileOrApplicationNotFound => "File or application not found.",
+            RecordNotFound => "Record not found.",
+            IncorrectParameters => "Incorrect parameters P1-P2.",
+            WrongLe(_) => "Wrong Le field.",
+            InstructionNotSupported => "Instruction code not supported or invalid.",
+            ClassNotSupported => "Class not supported.",
+            NoPreciseDiagnosis => "No precise diagnosis.",
+            Unknown(_, _) => "Unknown status.",
+        }
+    }
+
+    /// True if the command succeeded, and the response holds all of its data.
+    pub fn is_success(self) -> bool {
+        matches!(
+            self,
+            DesfireStatus::OperationOk | DesfireStatus::NoChanges | DesfireStatus::Success
+        )
+    }
+
+    /// True if the command succeeded, but the card has more data to send.
+    pub fn is_more_data(self) -> bool {
+        matches!(
+            self,
+            DesfireStatus::AdditionalFrame | DesfireStatus::BytesRemaining(_)
+        )
+    }
+
+    /// Ok if the command succeeded, even if more data is waiting. Otherwise the status as an error.
+    pub fn check(self) -> Result<DesfireStatus, StatusError> {
+        if self.is_success() || self.is_more_data() {
+            Ok(self)
+        } else {
+            Err(StatusError(self))
+        }
+    }
+}
+
+impl From<[u8; 2]> for DesfireStatus {
+    fn from(sw: [u8; 2]) -> Self {
+        DesfireStatus::from_sw(sw[0], sw[1])
+    }
+}
+
+impl fmt::Display for DesfireStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let [sw1, sw2] = self.sw();
+        write!(f, "{:02X}{:02X}: {}", sw1, sw2, self.description())
+    }
+}
+
+/// A status word that means the command failed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct StatusError(pub DesfireStatus);
+
+impl fmt::Display for StatusError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "The card returned an error: {}", self.0)
+    }
+}
+
+impl std::error::Error for StatusError {}
+
+impl From<DesfireStatus> for StatusError {
+    fn from(status: DesfireStatus) -> Self {
+        StatusError(status)
+    }
+}
+
+/// Splits a raw response into its data and its trailing status word.
+/// None if the response is too short to have a status word.
+pub fn split_response(response: &[u8]) -> Option<(&[u8], DesfireStatus)> {
+    if response.len() < 2 {
+        return None;
+    }
+    let (data, sw) = response.split_at(response.len() - 2);
+    Some((data, DesfireStatus::from_sw(sw[0], sw[1])))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::desfire::status::{split_response, DesfireStatus, StatusError};
+
+    #[test]
+    fn should_round_trip_every_status_word() {
+        for sw1 in [
+            0x61, 0x65, 0x67, 0x69, 0x6A, 0x6C, 0x6D, 0x6E, 0x6F, 0x90, 0x91,
+        ] {
+            for sw2 in 0..=255 {
+                assert_eq!(DesfireStatus::from_sw(sw1, sw2).sw(), [sw1, sw2]);
+            }
+        }
+    }
+
+    #[test]
+    fn should_split_responses() {
+        let (data, status) = split_response(&[0x01, 0x02, 0x91, 0xAF]).unwrap();
+        assert_eq!(data, [0x01, 0x02]);
+        assert!(status.is_more_data());
+
+        let (data, status) = split_response(&[0x91, 0x9D]).unwrap();
+        assert!(data.is_empty());
+        assert_eq!(
+            status.check(),
+            Err(StatusError(DesfireStatus::PermissionDenied))
+        );
+
+        assert!(split_response(&[0x90]).is_none());
+    }
+}
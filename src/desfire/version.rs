@@ -0,0 +1,214 @@
+//! Decoding the response to the DESFire GetVersion command.
+
+use crate::desfire::{transceive_chained, CardTransport, TransceiveError, GET_VERSION_COMMAND};
+
+/// The length of the GetVersion response, once its three frames are joined and their status words removed.
+pub const VERSION_RESPONSE_LENGTH: usize = 28;
+
+/// Version information for either the chip's hardware or its software.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ComponentVersion {
+    /// 0x04 is NXP.
+    pub vendor_id: u8,
+    pub chip_type: u8,
+    pub subtype: u8,
+    pub major_version: u8,
+    pub minor_version: u8,
+    /// The encoded storage size. See `CardVersion::memory_size_bytes`.
+    pub storage_size: u8,
+    /// 0x05 is ISO 14443-2 and -3.
+    pub protocol: u8,
+}
+
+impl ComponentVersion {
+    fn from_bytes(bytes: &[u8]) -> ComponentVersion {
+        ComponentVersion {
+            vendor_id: bytes[0],
+            chip_type: bytes[1],
+            subtype: bytes[2],
+            major_version: bytes[3],
+            minor_version: bytes[4],
+            storage_size: bytes[5],
+            protocol: bytes[6],
+        }
+    }
+}
+
+/// The generation of DESFire chip, as indicated by the hardware major version.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum DesfireGeneration {
+    /// The original DESFire, sometimes called EV0.
+    Ev0,
+    Ev1,
+    Ev2,
+    Ev3,
+    Unknown(u8),
+}
+
+impl From<u8> for DesfireGeneration {
+    fn from(major_version: u8) -> Self {
+        match major_version {
+            0x00 => DesfireGeneration::Ev0,
+            0x01 => DesfireGeneration::Ev1,
+            0x12 => DesfireGeneration::Ev2,
+            0x30 => DesfireGeneration::Ev3,
+            other => DesfireGeneration::Unknown(other),
+        }
+    }
+}
+
+/// Everything the card reports about itself in response to GetVersion.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CardVersion {
+    pub hardware: ComponentVersion,
+    pub software: ComponentVersion,
+    pub uid: [u8; 7],
+    pub batch_number: [u8; 5],
+    /// 1-52. 0 if the card doesn't report it.
+    pub production_week: u8,
+    /// The full year, e.g. 2019. 2000 if the card doesn't report it.
+    pub production_year: u16,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct VersionLengthError(pub usize);
+
+impl std::fmt::Display for VersionLengthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "GetVersion response was {} bytes long, expected {}.",
+            self.0, VERSION_RESPONSE_LENGTH
+        )
+    }
+}
+
+impl std::error::Error for VersionLengthError {}
+
+#[derive(Debug)]
+pub enum ReadVersionError<E> {
+    Transceive(TransceiveError<E>),
+    Length(VersionLengthError),
+}
+
+impl<E: std::fmt::Display> std::fmt::Display for ReadVersionError<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ReadVersionError::Transceive(e) => write!(f, "{}", e),
+            ReadVersionError::Length(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl<E: std::fmt::Debug + std::fmt::Display> std::error::Error for ReadVersionError<E> {}
+
+impl<E> From<TransceiveError<E>> for ReadVersionError<E> {
+    fn from(e: TransceiveError<E>) -> Self {
+        ReadVersionError::Transceive(e)
+    }
+}
+
+impl<E> From<VersionLengthError> for ReadVersionError<E> {
+    fn from(e: VersionLengthError) -> Self {
+        ReadVersionError::Length(e)
+    }
+}
+
+fn from_bcd(value: u8) -> u8 {
+    (value >> 4) * 10 + (value & 0x0F)
+}
+
+impl CardVersion {
+    /// Parses the GetVersion response, given as the data of all three frames joined together,
+    /// without their status words.
+    pub fn parse(bytes: &[u8]) -> Result<CardVersion, VersionLengthError> {
+        if bytes.len() != VERSION_RESPONSE_LENGTH {
+            return Err(VersionLengthError(bytes.len()));
+        }
+        let mut uid = [0; 7];
+        uid.copy_from_slice(&bytes[14..21]);
+        let mut batch_number = [0; 5];
+        batch_number.copy_from_slice(&bytes[21..26]);
+
+        Ok(CardVersion {
+            hardware: ComponentVersion::from_bytes(&bytes[0..7]),
+            software: ComponentVersion::from_bytes(&bytes[7..14]),
+            uid,
+            batch_number,
+            production_week: from_bcd(bytes[26]),
+            production_year: 2000 + u16::from(from_bcd(bytes[27])),
+        })
+    }
+
+    /// Sends GetVersion to the card, following up with READ_NEXT for as long as
+    /// the card says there's more data.
+    pub fn read<T: CardTransport + ?Sized>(
+        transport: &mut T,
+    ) -> Result<CardVersion, ReadVersionError<T::Error>> {
+        // The response always comes in three frames.
+        let data = transceive_chained(transport, &GET_VERSION_COMMAND, 3)?;
+        Ok(CardVersion::parse(&data)?)
+    }
+
+    pub fn generation(&self) -> DesfireGeneration {
+        DesfireGeneration::from(self.hardware.major_version)
+    }
+
+    /// The size of the card's user memory in bytes. The storage size byte encodes
+    /// the size as 2^(n / 2). If its lowest bit is set, the real size is somewhere between
+    /// that and the next power of two, and this returns the lower bound.
+    pub fn memory_size_bytes(&self) -> u32 {
+        1u32.checked_shl(u32::from(self.hardware.storage_size >> 1))
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::desfire::status::{DesfireStatus, StatusError};
+    use crate::desfire::version::{CardVersion, DesfireGeneration, ReadVersionError};
+    use crate::desfire::TransceiveError;
+
+    // Frames from a DESFire EV1 8K, with their status words.
+    const FRAMES: [&[u8]; 3] = [
+        &[0x04, 0x01, 0x01, 0x01, 0x00, 0x1A, 0x05, 0x91, 0xAF],
+        &[0x04, 0x01, 0x01, 0x01, 0x04, 0x1A, 0x05, 0x91, 0xAF],
+        &[
+            0x04, 0x52, 0x2E, 0x9A, 0x63, 0x38, 0x80, 0xBA, 0x44, 0x59, 0x58, 0x10, 0x37, 0x19,
+            0x91, 0x00,
+        ],
+    ];
+
+    #[test]
+    fn should_read_chained_frames() {
+        let mut frames = FRAMES.iter();
+        let mut sent = vec![];
+        let version = CardVersion::read(&mut |command: &[u8]| {
+            sent.push(command[1]);
+            Ok::<_, ()>(frames.next().unwrap().to_vec())
+        })
+        .unwrap();
+
+        assert_eq!(sent, vec![0x60, 0xAF, 0xAF]);
+        assert_eq!(version.hardware.vendor_id, 0x04);
+        assert_eq!(version.software.minor_version, 0x04);
+        assert_eq!(version.uid, [0x04, 0x52, 0x2E, 0x9A, 0x63, 0x38, 0x80]);
+        assert_eq!(version.batch_number, [0xBA, 0x44, 0x59, 0x58, 0x10]);
+        assert_eq!(version.production_week, 37);
+        assert_eq!(version.production_year, 2019);
+        assert_eq!(version.generation(), DesfireGeneration::Ev1);
+        assert_eq!(version.memory_size_bytes(), 8192);
+    }
+
+    #[test]
+    fn should_reject_error_responses() {
+        let result = CardVersion::read(&mut |_: &[u8]| Ok::<_, ()>(vec![0x91, 0x9D]));
+        assert!(matches!(
+            result,
+            Err(ReadVersionError::Transceive(TransceiveError::Status(
+                StatusError(DesfireStatus::PermissionDenied)
+            )))
+        ));
+        assert!(CardVersion::parse(&[0; 27]).is_err());
+    }
+}
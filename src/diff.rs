@@ -0,0 +1,353 @@
+//! Comparing two reads of the same card.
+
+use crate::eticket::ETicket;
+use crate::history::History;
+use crate::travelcard::TravelCard;
+use crate::validity::CardProduct;
+use chrono::prelude::*;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffError {
+    /// The two reads are of different cards.
+    DifferentCards { previous: String, current: String },
+}
+
+impl fmt::Display for DiffError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DiffError::DifferentCards { previous, current } => write!(
+                f,
+                "Can't compare different cards: {} and {}.",
+                previous, current
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DiffError {}
+
+/// A value that was different in the two reads.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Change<T> {
+    pub before: T,
+    pub after: T,
+}
+
+impl<T: PartialEq> Change<T> {
+    fn between(before: T, after: T) -> Option<Change<T>> {
+        if before == after {
+            None
+        } else {
+            Some(Change { before, after })
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct StoredValueLoad {
+    pub datetime: DateTime<Utc>,
+    pub value_cents: u32,
+    pub organization_id: u16,
+    pub device_num: u16,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PeriodLoad {
+    pub datetime: DateTime<Utc>,
+    pub length_days: u16,
+    pub price_cents: u32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum PeriodChangeKind {
+    /// The slot holds a period that doesn't continue the one it held before.
+    New,
+    /// The slot's period was extended, or a new one starts right after the old one ends.
+    Renewed,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PeriodChange {
+    pub slot: CardProduct,
+    pub kind: PeriodChangeKind,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+}
+
+/// Everything that changed between two reads of a card.
+#[derive(Debug)]
+pub struct CardDiff<'a> {
+    /// History entries in the newer read that aren't in the older one, oldest first.
+    pub new_history: Vec<&'a History>,
+    pub balance: Option<Change<u32>>,
+    pub new_load: Option<StoredValueLoad>,
+    pub new_period_load: Option<PeriodLoad>,
+    pub period_changes: Vec<PeriodChange>,
+    /// The newer read's eTicket, if a different ticket was bought in between.
+    pub new_e_ticket: Option<&'a ETicket>,
+    pub transaction_counter: Option<Change<u32>>,
+    pub action_list_counter: Option<Change<u32>>,
+}
+
+impl CardDiff<'_> {
+    pub fn is_empty(&self) -> bool {
+        self.new_history.is_empty()
+            && self.balance.is_none()
+            && self.new_load.is_none()
+            && self.new_period_load.is_none()
+            && self.period_changes.is_empty()
+            && self.new_e_ticket.is_none()
+            && self.transaction_counter.is_none()
+            && self.action_list_counter.is_none()
+    }
+}
+
+impl TravelCard {
+    /// Lists what changed on the card since the `previous` read of it.
+    pub fn diff<'a>(&'a self, previous: &TravelCard) -> Result<CardDiff<'a>, DiffError> {
+        if self.application_instance_id != previous.application_instance_id {
+            return Err(DiffError::DifferentCards {
+                previous: previous.application_instance_id.clone(),
+                current: self.application_instance_id.clone(),
+            });
+        }
+
+        let mut new_history: Vec<&History> = self
+            .history
+            .iter()
+            .filter(|entry| !previous.history.contains(entry))
+            .collect();
+        new_history.sort_by_key(|entry| entry.boarding_datetime);
+
+        let new_load = if (self.last_load_datetime, self.last_load_value)
+            != (previous.last_load_datetime, previous.last_load_value)
+        {
+            Some(StoredValueLoad {
+                datetime: self.last_load_datetime,
+                value_cents: self.last_load_value,
+                organization_id: self.last_load_organization_id,
+                device_num: self.last_load_device_num,
+            })
+        } else {
+            None
+        };
+
+        let pass = &self.period_pass;
+        let new_period_load =
+            if pass.loaded_period_datetime != previous.period_pass.loaded_period_datetime {
+                Some(PeriodLoad {
+                    datetime: pass.loaded_period_datetime,
+                    length_days: pass.loaded_period_length,
+                    price_cents: pass.loaded_period_price,
+                })
+            } else {
+                None
+            };
+
+        let products = [CardProduct::PeriodPass1, CardProduct::PeriodPass2];
+        let period_changes = products
+            .iter()
+            .zip(pass.slots().iter().zip(previous.period_pass.slots().iter()))
+            .filter(|(_, (after, before))| {
+                (after.start_date, after.end_date) != (before.start_date, before.end_date)
+            })
+            .map(|(slot, (after, before))| {
+                let continues = after.start_date <= before.end_date.succ_opt().unwrap()
+                    && after.end_date > before.end_date;
+                PeriodChange {
+                    slot: *slot,
+                    kind: if continues {
+                        PeriodChangeKind::Renewed
+                    } else {
+                        PeriodChangeKind::New
+                    },
+                    start_date: after.start_date,
+                    end_date: after.end_date,
+                }
+            })
+            .collect();
+
+        let new_e_ticket = if (
+            self.e_ticket.sale_datetime,
+            self.e_ticket.validity_start_datetime,
+        ) != (
+            previous.e_ticket.sale_datetime,
+            previous.e_ticket.validity_start_datetime,
+        ) {
+            Some(&self.e_ticket)
+        } else {
+            None
+        };
+
+        Ok(CardDiff {
+            new_history,
+            balance: Change::between(previous.stored_value_cents, self.stored_value_cents),
+            new_load,
+            new_period_load,
+            period_changes,
+            new_e_ticket,
+            transaction_counter: Change::between(
+                previous.application_transaction_counter,
+                self.application_transaction_counter,
+            ),
+            action_list_counter: Change::between(
+                previous.action_list_counter,
+                self.action_list_counter,
+            ),
+        })
+    }
+}
+
+fn euros(cents: u32) -> f64 {
+    f64::from(cents) / 100.0
+}
+
+impl fmt::Display for CardDiff<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "No changes.");
+        }
+        for entry in &self.new_history {
+            writeln!(
+                f,
+                "New trip: {:?} at {}, {:.2} € for {} traveller(s)",
+                entry.transaction_type,
+                entry.boarding_datetime,
+                euros(u32::from(entry.ticket_fare_cents)),
+                entry.group_size
+            )?;
+        }
+        if let Some(balance) = self.balance {
+            writeln!(
+                f,
+                "Balance: {:.2} € -> {:.2} €",
+                euros(balance.before),
+                euros(balance.after)
+            )?;
+        }
+        if let Some(load) = self.new_load {
+            writeln!(
+                f,
+                "Value loaded: {:.2} € at {}",
+                euros(load.value_cents),
+                load.datetime
+            )?;
+        }
+        if let Some(load) = self.new_period_load {
+            writeln!(
+                f,
+                "Period loaded: {} days for {:.2} € at {}",
+                load.length_days,
+                euros(load.price_cents),
+                load.datetime
+            )?;
+        }
+        for change in &self.period_changes {
+            writeln!(
+                f,
+                "{:?} {:?}: {} - {}",
+                change.slot, change.kind, change.start_date, change.end_date
+            )?;
+        }
+        if let Some(ticket) = self.new_e_ticket {
+            writeln!(
+                f,
+                "New eTicket: valid {} - {}",
+                ticket.validity_start_datetime,
+                ticket.effective_validity_end()
+            )?;
+        }
+        if let Some(counter) = self.transaction_counter {
+            writeln!(
+                f,
+                "Transaction counter: {} -> {}",
+                counter.before, counter.after
+            )?;
+        }
+        if let Some(counter) = self.action_list_counter {
+            writeln!(
+                f,
+                "Action list counter: {} -> {}",
+                counter.before, counter.after
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::diff::{Change, DiffError, PeriodChangeKind};
+    use crate::test_cards::{dump_datetime, en1545_date, trip_dump, CardDump};
+    use crate::validity::CardProduct;
+
+    fn previous_dump() -> CardDump {
+        let mut dump = CardDump::new();
+        dump.instance_id(1);
+        dump.counters(10, 2);
+        dump.stored_value(1000);
+        dump.period_pass_1(0b000_001, en1545_date(2020, 2, 1), en1545_date(2020, 2, 29));
+        dump.push_history(trip_dump(true, dump_datetime(2020, 3, 1, 8, 0), 280, 1000));
+        dump
+    }
+
+    #[test]
+    fn should_list_changes_between_reads() {
+        let previous = previous_dump().build();
+        let mut dump = previous_dump();
+        dump.counters(12, 2);
+        dump.stored_value(720);
+        dump.period_pass_1(0b000_001, en1545_date(2020, 3, 1), en1545_date(2020, 3, 30));
+        dump.e_ticket(
+            0b000_010,
+            dump_datetime(2020, 3, 2, 8, 0),
+            dump_datetime(2020, 3, 2, 9, 20),
+        );
+        dump.push_history(trip_dump(true, dump_datetime(2020, 3, 2, 8, 0), 280, 720));
+        let current = dump.build();
+
+        let diff = current.diff(&previous).unwrap();
+        assert_eq!(diff.new_history.len(), 1);
+        assert_eq!(diff.new_history[0].remaining_value, 720);
+        assert_eq!(
+            diff.balance,
+            Some(Change {
+                before: 1000,
+                after: 720
+            })
+        );
+        assert!(diff.new_load.is_none());
+        assert_eq!(diff.period_changes.len(), 1);
+        assert_eq!(diff.period_changes[0].slot, CardProduct::PeriodPass1);
+        assert_eq!(diff.period_changes[0].kind, PeriodChangeKind::Renewed);
+        assert!(diff.new_e_ticket.is_some());
+        assert_eq!(
+            diff.transaction_counter,
+            Some(Change {
+                before: 10,
+                after: 12
+            })
+        );
+        assert!(diff.action_list_counter.is_none());
+    }
+
+    #[test]
+    fn should_find_nothing_between_identical_reads() {
+        let card = previous_dump().build();
+        let diff = card.diff(&previous_dump().build()).unwrap();
+        assert!(diff.is_empty());
+        assert_eq!(diff.to_string(), "No changes.\n");
+    }
+
+    #[test]
+    fn should_refuse_to_compare_different_cards() {
+        let previous = previous_dump().build();
+        let mut dump = previous_dump();
+        dump.instance_id(2);
+        assert!(matches!(
+            dump.build().diff(&previous),
+            Err(DiffError::DifferentCards { .. })
+        ));
+    }
+}
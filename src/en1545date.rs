@@ -5,8 +5,8 @@ use lazy_static::*;
 
 lazy_static! {
     static ref EN1545_ZERO_DATE: NaiveDateTime = NaiveDateTime::new(
-        NaiveDate::from_ymd(1997, 1, 1),
-        NaiveTime::from_hms(0, 0, 0)
+        NaiveDate::from_ymd_opt(1997, 1, 1).unwrap(),
+        NaiveTime::from_hms_opt(0, 0, 0).unwrap()
     );
 }
 
@@ -17,6 +17,26 @@ pub fn from_en1545_date(date: u16) -> DateTime<Utc> {
     from_en1545_date_and_time(date, 0u16)
 }
 
+/// Convert from En1545 (number of days since 1997-01-01) to a calendar date in Helsinki local time.
+/// # Arguments
+///  * `date` - The date in En1545 format (number of days since 1997-01-01).
+pub fn from_en1545_date_local(date: u16) -> NaiveDate {
+    EN1545_ZERO_DATE.date() + Duration::days(i64::from(date))
+}
+
+/// The instant at which the given Helsinki calendar date begins, in UTC.
+pub(crate) fn helsinki_midnight(date: NaiveDate) -> DateTime<Utc> {
+    Helsinki
+        .from_local_datetime(&date.and_time(NaiveTime::MIN))
+        .unwrap()
+        .with_timezone(&Utc)
+}
+
+/// The Helsinki calendar date on which the given instant falls.
+pub(crate) fn helsinki_date(instant: DateTime<Utc>) -> NaiveDate {
+    instant.with_timezone(&Helsinki).date_naive()
+}
+
 /// Convert from En1545 (number of days since 1997-01-01, and number of minute since 00:00) to a standard UTC DateTime.
 /// # Arguments
 ///  * `date` - The date in En1545 format (number of days since 1997-01-01).
@@ -34,14 +54,14 @@ pub fn from_en1545_date_and_time(date: u16, time: u16) -> DateTime<Utc> {
 
 #[cfg(test)]
 mod test {
-    use crate::en1545date::{from_en1545_date, from_en1545_date_and_time};
+    use crate::en1545date::{from_en1545_date, from_en1545_date_and_time, from_en1545_date_local};
     use chrono::prelude::*;
 
     #[test]
     fn should_handle_summer_dates() {
         let value = from_en1545_date(19514); // Should be 2050-06-06, in Helsinki time.
                                              // -3h in UTC because summer time is active.
-        let expected = Utc.ymd(2050, 6, 5).and_hms(21, 0, 0);
+        let expected = Utc.with_ymd_and_hms(2050, 6, 5, 21, 0, 0).unwrap();
         assert_eq!(value, expected);
     }
 
@@ -49,7 +69,7 @@ mod test {
     fn should_handle_winter_dates() {
         let actual = from_en1545_date(0); // 1997-01-01, in Helsinki time.
                                           // Only -2h from UTC.
-        let expected = Utc.ymd(1996, 12, 31).and_hms(22, 0, 0);
+        let expected = Utc.with_ymd_and_hms(1996, 12, 31, 22, 0, 0).unwrap();
         assert_eq!(actual, expected);
     }
 
@@ -57,7 +77,7 @@ mod test {
     fn should_handle_summer_datetimes() {
         let value = from_en1545_date_and_time(19514, 150); // Should be 2050-06-06, 2:30AM in Helsinki time.
                                                            // Then -3h in UTC because summer time is active.
-        let expected = Utc.ymd(2050, 6, 5).and_hms(23, 30, 0);
+        let expected = Utc.with_ymd_and_hms(2050, 6, 5, 23, 30, 0).unwrap();
         assert_eq!(value, expected);
     }
 
@@ -65,7 +85,14 @@ mod test {
     fn should_handle_winter_datetimes() {
         let value = from_en1545_date_and_time(0, 240); // should be 1997-01-01 4:00AM.
                                                        // Then -2h in UTC because winter time is active
-        let expected = Utc.ymd(1997, 1, 1).and_hms(2, 0, 0);
+        let expected = Utc.with_ymd_and_hms(1997, 1, 1, 2, 0, 0).unwrap();
         assert_eq!(value, expected);
     }
+
+    #[test]
+    fn local_dates_should_not_shift_to_previous_utc_day() {
+        let actual = from_en1545_date_local(19514); // 2050-06-06, in Helsinki time.
+        let expected = NaiveDate::from_ymd_opt(2050, 6, 6).unwrap();
+        assert_eq!(actual, expected);
+    }
 }
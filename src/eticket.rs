@@ -1,15 +1,17 @@
+use crate::clock::Clock;
 use crate::conversion::*;
 use crate::en1545date::*;
 use crate::models::*;
 use chrono::prelude::*;
+use chrono::Duration;
 use num_traits::Zero;
 
 #[derive(Debug)]
 pub struct ETicket {
     /// If ProductCodeGroup is > 0, this returns ProductCodeGroup
     pub product_code: ProductCode,
-    /// If CustomerProfileGroup is > 0, this returns CustomerProfileGroup.
-    pub customer_profile: u8,
+    /// If CustomerProfileGroup is > 0, this returns CustomerProfileGroup as a `Group` profile.
+    pub customer_profile: TicketCustomerProfile,
     pub language: Language,
     /// If ValidityLengthGroup is > 0 this returns ValidityLengthGroup.
     pub validity_length: ValidityLength,
@@ -47,6 +49,71 @@ pub struct ETicket {
     pub boarding_area: BoardingArea,
 }
 
+/// Where an eTicket is in its lifetime at a given time.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ETicketState {
+    /// The ticket's validity hasn't started yet.
+    NotYetValid { starts_at: DateTime<Utc> },
+    /// The ticket is valid, but hasn't been used for boarding yet.
+    Active { remaining: Duration },
+    /// The ticket has been used for boarding, and transfers can be made until it runs out.
+    InTransferWindow {
+        boarded_at: DateTime<Utc>,
+        remaining: Duration,
+    },
+    /// The ticket has run out.
+    Expired { expired_at: DateTime<Utc> },
+    /// The card marks the ticket as not valid, even though its validity period hasn't ended.
+    Invalidated,
+}
+
+impl ETicket {
+    /// The instant this ticket stops being valid. If the card doesn't have an end time
+    /// that comes after the start time, it's worked out from `validity_length` instead.
+    pub fn effective_validity_end(&self) -> DateTime<Utc> {
+        if self.validity_end_datetime > self.validity_start_datetime {
+            self.validity_end_datetime
+        } else {
+            self.validity_length.end_from(self.validity_start_datetime)
+        }
+    }
+
+    /// The state of this ticket at the given time.
+    pub fn state_at(&self, time: DateTime<Utc>) -> ETicketState {
+        let end = self.effective_validity_end();
+        if !self.validity_status {
+            return if time >= end {
+                ETicketState::Expired { expired_at: end }
+            } else {
+                ETicketState::Invalidated
+            };
+        }
+        if time < self.validity_start_datetime {
+            ETicketState::NotYetValid {
+                starts_at: self.validity_start_datetime,
+            }
+        } else if time >= end {
+            ETicketState::Expired { expired_at: end }
+        } else if self.validity_start_datetime <= self.boarding_datetime
+            && self.boarding_datetime <= time
+        {
+            ETicketState::InTransferWindow {
+                boarded_at: self.boarding_datetime,
+                remaining: end - time,
+            }
+        } else {
+            ETicketState::Active {
+                remaining: end - time,
+            }
+        }
+    }
+
+    /// The state of this ticket right now.
+    pub fn state(&self, clock: &dyn Clock) -> ETicketState {
+        self.state_at(clock.now())
+    }
+}
+
 pub fn create_e_ticket(e_ticket: &[u8]) -> ETicket {
     let product_code_type = get_bits_as_u8(e_ticket, 0, 1);
     let product_code_value = get_bits_as_u16(e_ticket, 1, 14);
@@ -55,7 +122,6 @@ pub fn create_e_ticket(e_ticket: &[u8]) -> ETicket {
 
     let customer_profile_value = get_bits_as_u8(e_ticket, 29, 5);
     let customer_profile_group_value = get_bits_as_u8(e_ticket, 34, 5);
-    let customer_profile = single_or_group(customer_profile_value, customer_profile_group_value);
     let language_code = get_bits_as_u8(e_ticket, 39, 2);
 
     let validity_length_type_value = get_bits_as_u8(e_ticket, 41, 2);
@@ -111,7 +177,10 @@ pub fn create_e_ticket(e_ticket: &[u8]) -> ETicket {
 
     ETicket {
         product_code: ProductCode::new(product_code_type, product_code),
-        customer_profile,
+        customer_profile: TicketCustomerProfile::new(
+            customer_profile_value,
+            customer_profile_group_value,
+        ),
         language: Language::from(language_code),
         validity_length: ValidityLength::new(validity_length_type, validity_length),
         validity_area: ValidityArea::new(validity_area_type, validity_area_value),
@@ -162,3 +231,172 @@ fn single_or_group<T: Zero + PartialOrd>(single: T, group: T) -> T {
         single
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::conversion::set_bits;
+    use crate::eticket::{create_e_ticket, ETicketState};
+    use crate::models::{BoardingArea, ValidityArea, ValidityZone, ZoneCircle};
+    use crate::test_cards::{dump_datetime, helsinki, CardDump};
+    use chrono::Duration;
+
+    const E_TICKET_LENGTH: usize = 45;
+
+    /// A synthetic eTicket file, built from the documented field layout and the assumed
+    /// zone circle encoding rather than read from a card: an 80-minute adult single ticket
+    /// for zones AB sold on 2020-06-15, boarded at 10:05 in the BC circle.
+    const E_TICKET_FILE: [u8; E_TICKET_LENGTH] = [
+        0x80, 0x04, 0x00, 0x00, 0x40, 0x0A, 0x00, 0x04, 0x0C, 0x2E, 0xCA, 0x22, 0x69, 0x02, 0x30,
+        0x00, 0x00, 0x20, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x84, 0x2E, 0xC9, 0x62, 0x17,
+        0x65, 0x50, 0x00, 0x00, 0x00, 0x06, 0x17, 0x64, 0xBA, 0x2B, 0xD8, 0x00, 0x02, 0x28, 0x00,
+    ];
+
+    /// A dump of a single-ticket eTicket valid in zones AB, last boarded in the BC circle.
+    fn ab_ticket_boarded_in_bc() -> [u8; E_TICKET_LENGTH] {
+        let mut dump = [0u8; E_TICKET_LENGTH];
+        set_bits(&mut dump, 61, 2, u64::from(ValidityArea::NEW_ZONE_TYPE));
+        set_bits(&mut dump, 63, 6, 0b000_001); // A to B
+        set_bits(&mut dump, 342, 2, 2); // Zone circle
+        set_bits(&mut dump, 344, 6, 0b001_010); // B to C
+        dump
+    }
+
+    #[test]
+    fn should_decode_boarding_zone_circle() {
+        let e_ticket = create_e_ticket(&ab_ticket_boarded_in_bc());
+        match &e_ticket.boarding_area {
+            BoardingArea::ZoneCircle(circle) => {
+                assert_eq!(
+                    *circle,
+                    ZoneCircle {
+                        from: ValidityZone::ZoneB,
+                        to: ValidityZone::ZoneC
+                    }
+                );
+                assert_eq!(u8::from(circle), 0b001_010);
+            }
+            other => panic!("Expected a ZoneCircle, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn should_decode_zone_circle_from_full_file() {
+        let e_ticket = create_e_ticket(&E_TICKET_FILE);
+        assert_eq!(
+            e_ticket.validity_area.covers(&e_ticket.boarding_area),
+            Some(false)
+        );
+        match &e_ticket.boarding_area {
+            BoardingArea::ZoneCircle(circle) => assert_eq!(circle.zones().to_string(), "BC"),
+            other => panic!("Expected a ZoneCircle, got {:?}", other),
+        }
+        assert_eq!(e_ticket.ticket_fare_cents, 280);
+        assert_eq!(e_ticket.boarding_datetime, helsinki(2020, 6, 15, 10, 5));
+        assert_eq!(
+            e_ticket.effective_validity_end(),
+            helsinki(2020, 6, 15, 11, 20)
+        );
+    }
+
+    #[test]
+    fn validity_area_should_not_cover_wider_boarding_circle() {
+        let e_ticket = create_e_ticket(&ab_ticket_boarded_in_bc());
+        assert_eq!(
+            e_ticket.validity_area.covers(&e_ticket.boarding_area),
+            Some(false)
+        );
+
+        let mut dump = ab_ticket_boarded_in_bc();
+        set_bits(&mut dump, 63, 6, 0b000_010); // A to C
+        let e_ticket = create_e_ticket(&dump);
+        assert_eq!(
+            e_ticket.validity_area.covers(&e_ticket.boarding_area),
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn state_should_move_from_active_to_transfer_window_to_expired() {
+        let mut dump = CardDump::new();
+        dump.e_ticket(
+            0b000_001,
+            dump_datetime(2020, 6, 15, 10, 0),
+            dump_datetime(2020, 6, 15, 11, 20),
+        );
+        dump.e_ticket_boarding(dump_datetime(2020, 6, 15, 10, 5));
+        let e_ticket = dump.build().e_ticket;
+
+        assert_eq!(
+            e_ticket.state_at(helsinki(2020, 6, 15, 9, 59)),
+            ETicketState::NotYetValid {
+                starts_at: helsinki(2020, 6, 15, 10, 0)
+            }
+        );
+        assert_eq!(
+            e_ticket.state_at(helsinki(2020, 6, 15, 10, 2)),
+            ETicketState::Active {
+                remaining: Duration::minutes(78)
+            }
+        );
+        assert_eq!(
+            e_ticket.state_at(helsinki(2020, 6, 15, 11, 0)),
+            ETicketState::InTransferWindow {
+                boarded_at: helsinki(2020, 6, 15, 10, 5),
+                remaining: Duration::minutes(20)
+            }
+        );
+        assert_eq!(
+            e_ticket.state_at(helsinki(2020, 6, 15, 11, 20)),
+            ETicketState::Expired {
+                expired_at: helsinki(2020, 6, 15, 11, 20)
+            }
+        );
+    }
+
+    #[test]
+    fn cleared_validity_status_should_invalidate_ticket() {
+        let mut dump = CardDump::new();
+        dump.e_ticket(
+            0b000_001,
+            dump_datetime(2020, 6, 15, 10, 0),
+            dump_datetime(2020, 6, 15, 11, 20),
+        );
+        dump.e_ticket_validity_status(false);
+        let e_ticket = dump.build().e_ticket;
+
+        assert!(!e_ticket.validity_status);
+        assert_eq!(
+            e_ticket.state_at(helsinki(2020, 6, 15, 10, 30)),
+            ETicketState::Invalidated
+        );
+        assert_eq!(
+            e_ticket.state_at(helsinki(2020, 6, 15, 12, 0)),
+            ETicketState::Expired {
+                expired_at: helsinki(2020, 6, 15, 11, 20)
+            }
+        );
+    }
+
+    #[test]
+    fn missing_end_should_fall_back_to_validity_length() {
+        let mut dump = CardDump::new();
+        dump.e_ticket(
+            0b000_001,
+            dump_datetime(2020, 6, 15, 10, 0),
+            dump_datetime(1997, 1, 1, 0, 0),
+        );
+        dump.e_ticket_validity_length(0, 80); // 80 minutes
+        let e_ticket = dump.build().e_ticket;
+        assert_eq!(
+            e_ticket.effective_validity_end(),
+            helsinki(2020, 6, 15, 11, 20)
+        );
+
+        dump.e_ticket_validity_length(3, 2); // 2 days
+        let e_ticket = dump.build().e_ticket;
+        assert_eq!(
+            e_ticket.effective_validity_end(),
+            helsinki(2020, 6, 17, 0, 0)
+        );
+    }
+}
@@ -0,0 +1,609 @@
+//! Fare tables, and the prices derived from them.
+//!
+//! HSL's prices change every year, so fare tables are normally loaded from a file with
+//! `FareSchedule::load`. Either TOML or JSON can be used; TOML looks like this:
+//!
+//! ```toml
+//! [[tables]]
+//! effective_from = "2020-01-01"
+//! effective_to = "2020-12-31" # Optional, inclusive. Leave out for open-ended tables.
+//!
+//! [[tables.fares]]
+//! product = "single"
+//! zones = "AB"
+//! profile = "adult"
+//! price_cents = 280
+//!
+//! [[tables.fares]]
+//! product = { period = 30 } # A 30-day period product.
+//! zones = "AB"
+//! profile = "adult"
+//! price_cents = 5970
+//! ```
+//!
+//! `profile` is a snake_case `CustomerProfile` name, e.g. "reduced_mobility", or a raw profile number.
+
+use crate::en1545date::helsinki_date;
+use crate::history::History;
+use crate::models::{CustomerProfile, ZoneSet};
+use crate::travelcard::TravelCard;
+use chrono::prelude::*;
+use serde::Deserialize;
+use std::path::Path;
+
+/// A price list: what one traveller of a given customer profile pays
+/// for a product valid in a given set of zones.
+#[derive(Debug, Default, Clone)]
+pub struct FareTable {
+    fares: Vec<Fare>,
+}
+
+/// The kind of product a fare is for.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FareProduct {
+    /// A single ticket, paid from the card's stored value.
+    Single,
+    /// A period product valid for the given number of days.
+    Period(u16),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Fare {
+    pub product: FareProduct,
+    pub zones: ZoneSet,
+    pub profile: CustomerProfile,
+    pub price_cents: u32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FareError {
+    /// The fare table has no ticket covering the requested zones for the requested profile.
+    NoFare,
+    /// A group size of 0 was given. Like `History::group_size`, it counts every traveller.
+    EmptyGroup,
+    /// No zones were given. Every fare would trivially cover them.
+    NoZones,
+    /// The group's total price doesn't fit in a `u32` of cents.
+    Overflow,
+}
+
+impl std::fmt::Display for FareError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            FareError::NoFare => write!(f, "No fare covers the requested zones and profile."),
+            FareError::EmptyGroup => write!(f, "A group must have at least one traveller."),
+            FareError::NoZones => write!(f, "At least one zone must be given."),
+            FareError::Overflow => write!(f, "The total price is too large to represent."),
+        }
+    }
+}
+
+impl std::error::Error for FareError {}
+
+/// The price of a ticket, and whether a card's stored value covers it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FareQuote {
+    /// The fare that was used. Its zones may be wider than the ones asked for, if that was
+    /// the cheapest ticket covering them.
+    pub fare: Fare,
+    pub group_size: u8,
+    /// The total price for the whole group.
+    pub price_cents: u32,
+    pub balance_cents: u32,
+    pub is_covered: bool,
+    /// How much value would need to be loaded to afford the ticket. 0 if it's covered.
+    pub shortfall_cents: u32,
+}
+
+impl FareTable {
+    pub fn new() -> FareTable {
+        FareTable::default()
+    }
+
+    /// Adds a single-ticket fare.
+    pub fn insert(&mut self, zones: ZoneSet, profile: CustomerProfile, price_cents: u32) {
+        self.insert_product(FareProduct::Single, zones, profile, price_cents);
+    }
+
+    pub fn insert_product(
+        &mut self,
+        product: FareProduct,
+        zones: ZoneSet,
+        profile: CustomerProfile,
+        price_cents: u32,
+    ) {
+        self.fares.push(Fare {
+            product,
+            zones,
+            profile,
+            price_cents,
+        });
+    }
+
+    pub fn fares(&self) -> &[Fare] {
+        &self.fares
+    }
+
+    /// The cheapest single-ticket fare for the given profile whose zones cover all of the given zones.
+    pub fn find(&self, zones: ZoneSet, profile: CustomerProfile) -> Option<&Fare> {
+        self.find_product(FareProduct::Single, zones, profile)
+    }
+
+    /// The cheapest fare for the given product and profile whose zones cover all of the given zones.
+    /// None if no zones are given.
+    pub fn find_product(
+        &self,
+        product: FareProduct,
+        zones: ZoneSet,
+        profile: CustomerProfile,
+    ) -> Option<&Fare> {
+        if zones.is_empty() {
+            return None;
+        }
+        self.fares
+            .iter()
+            .filter(|fare| {
+                fare.product == product && fare.profile == profile && fare.zones.is_superset(&zones)
+            })
+            .min_by_key(|fare| fare.price_cents)
+    }
+
+    /// The total price for a group of `group_size` travellers with the given profile,
+    /// travelling in the given zones.
+    pub fn price(
+        &self,
+        zones: ZoneSet,
+        profile: CustomerProfile,
+        group_size: u8,
+    ) -> Result<u32, FareError> {
+        Ok(self.price_fare(zones, profile, group_size)?.1)
+    }
+
+    fn price_fare(
+        &self,
+        zones: ZoneSet,
+        profile: CustomerProfile,
+        group_size: u8,
+    ) -> Result<(Fare, u32), FareError> {
+        if group_size == 0 {
+            return Err(FareError::EmptyGroup);
+        }
+        if zones.is_empty() {
+            return Err(FareError::NoZones);
+        }
+        let fare = self.find(zones, profile).ok_or(FareError::NoFare)?;
+        let price_cents = fare
+            .price_cents
+            .checked_mul(u32::from(group_size))
+            .ok_or(FareError::Overflow)?;
+        Ok((*fare, price_cents))
+    }
+
+    /// Prices a ticket, and checks it against the given stored value balance.
+    pub fn quote(
+        &self,
+        zones: ZoneSet,
+        profile: CustomerProfile,
+        group_size: u8,
+        balance_cents: u32,
+    ) -> Result<FareQuote, FareError> {
+        let (fare, price_cents) = self.price_fare(zones, profile, group_size)?;
+        Ok(FareQuote {
+            fare,
+            group_size,
+            price_cents,
+            balance_cents,
+            is_covered: balance_cents >= price_cents,
+            shortfall_cents: price_cents.saturating_sub(balance_cents),
+        })
+    }
+}
+
+/// A fare table, and the Helsinki calendar dates on which it applies.
+#[derive(Debug, Clone)]
+pub struct DatedFareTable {
+    pub effective_from: NaiveDate,
+    /// Inclusive. None if the table hasn't been superseded.
+    pub effective_to: Option<NaiveDate>,
+    pub table: FareTable,
+}
+
+impl DatedFareTable {
+    pub fn applies_on(&self, date: NaiveDate) -> bool {
+        self.effective_from <= date && self.effective_to.is_none_or(|to| date <= to)
+    }
+}
+
+/// Every fare table that has applied over time.
+#[derive(Debug, Default, Clone)]
+pub struct FareSchedule {
+    tables: Vec<DatedFareTable>,
+}
+
+#[derive(Debug)]
+pub enum FareScheduleError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    Toml(toml::de::Error),
+    /// The file parsed, but contains a value that doesn't make sense, e.g. an unknown zone.
+    Invalid(String),
+}
+
+impl std::fmt::Display for FareScheduleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            FareScheduleError::Io(e) => write!(f, "Failed to read fare file: {}", e),
+            FareScheduleError::Json(e) => write!(f, "Failed to parse fare JSON: {}", e),
+            FareScheduleError::Toml(e) => write!(f, "Failed to parse fare TOML: {}", e),
+            FareScheduleError::Invalid(e) => write!(f, "Invalid fare data: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for FareScheduleError {}
+
+impl From<std::io::Error> for FareScheduleError {
+    fn from(e: std::io::Error) -> Self {
+        FareScheduleError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for FareScheduleError {
+    fn from(e: serde_json::Error) -> Self {
+        FareScheduleError::Json(e)
+    }
+}
+
+impl From<toml::de::Error> for FareScheduleError {
+    fn from(e: toml::de::Error) -> Self {
+        FareScheduleError::Toml(e)
+    }
+}
+
+// --- On-disk format ---
+#[derive(Deserialize)]
+struct FareScheduleFile {
+    #[serde(default)]
+    tables: Vec<FareTableEntry>,
+}
+
+#[derive(Deserialize)]
+struct FareTableEntry {
+    effective_from: String,
+    effective_to: Option<String>,
+    #[serde(default)]
+    fares: Vec<FareEntry>,
+}
+
+#[derive(Deserialize)]
+struct FareEntry {
+    product: FareProduct,
+    zones: String,
+    profile: String,
+    price_cents: u32,
+}
+
+fn parse_date(date: &str) -> Result<NaiveDate, FareScheduleError> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map_err(|e| FareScheduleError::Invalid(format!("'{}' is not a date: {}", date, e)))
+}
+
+impl FareSchedule {
+    pub fn new() -> FareSchedule {
+        FareSchedule::default()
+    }
+
+    /// Loads a schedule from the file at the given path. Files ending in `.json` are read
+    /// as JSON, and everything else as TOML.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<FareSchedule, FareScheduleError> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => FareSchedule::from_json(&contents),
+            _ => FareSchedule::from_toml(&contents),
+        }
+    }
+
+    pub fn from_json(json: &str) -> Result<FareSchedule, FareScheduleError> {
+        FareSchedule::from_file(serde_json::from_str(json)?)
+    }
+
+    pub fn from_toml(toml: &str) -> Result<FareSchedule, FareScheduleError> {
+        FareSchedule::from_file(toml::from_str(toml)?)
+    }
+
+    fn from_file(file: FareScheduleFile) -> Result<FareSchedule, FareScheduleError> {
+        let mut schedule = FareSchedule::new();
+        for entry in file.tables {
+            let mut table = FareTable::new();
+            for fare in entry.fares {
+                let zones = fare
+                    .zones
+                    .parse()
+                    .map_err(|e| FareScheduleError::Invalid(format!("{}", e)))?;
+                let profile = fare.profile.parse().map_err(FareScheduleError::Invalid)?;
+                table.insert_product(fare.product, zones, profile, fare.price_cents);
+            }
+            let effective_from = parse_date(&entry.effective_from)?;
+            let effective_to = match entry.effective_to {
+                Some(to) => Some(parse_date(&to)?),
+                None => None,
+            };
+            if let Some(to) = effective_to.filter(|to| *to < effective_from) {
+                return Err(FareScheduleError::Invalid(format!(
+                    "A table effective from {} can't end earlier, on {}.",
+                    effective_from, to
+                )));
+            }
+            schedule.insert(DatedFareTable {
+                effective_from,
+                effective_to,
+                table,
+            });
+        }
+        Ok(schedule)
+    }
+
+    pub fn insert(&mut self, table: DatedFareTable) {
+        self.tables.push(table);
+    }
+
+    pub fn tables(&self) -> &[DatedFareTable] {
+        &self.tables
+    }
+
+    /// The table that applies on the given Helsinki calendar date. If several do,
+    /// the one that took effect most recently.
+    pub fn table_for(&self, date: NaiveDate) -> Option<&FareTable> {
+        self.tables
+            .iter()
+            .filter(|t| t.applies_on(date))
+            .max_by_key(|t| t.effective_from)
+            .map(|t| &t.table)
+    }
+
+    /// The table that applied at the given instant.
+    pub fn table_at(&self, time: DateTime<Utc>) -> Option<&FareTable> {
+        self.table_for(helsinki_date(time))
+    }
+
+    /// The single-ticket fares that explain what was charged for the given history entry,
+    /// using the prices that applied when it happened. None if no table applied then.
+    /// An empty list means nothing in that table matches the charged amount.
+    pub fn explain_history_fare(&self, entry: &History) -> Option<Vec<&Fare>> {
+        let table = self.table_at(entry.boarding_datetime)?;
+        let charged = u32::from(entry.ticket_fare_cents);
+        let group_size = u32::from(entry.group_size);
+        Some(
+            table
+                .fares()
+                .iter()
+                .filter(|fare| {
+                    fare.product == FareProduct::Single
+                        && group_size > 0
+                        && fare.price_cents.checked_mul(group_size) == Some(charged)
+                })
+                .collect(),
+        )
+    }
+}
+
+impl TravelCard {
+    /// Prices a single ticket from the given fare table, and checks whether the card's
+    /// stored value covers it.
+    pub fn quote_fare(
+        &self,
+        fare_table: &FareTable,
+        zones: ZoneSet,
+        profile: CustomerProfile,
+        group_size: u8,
+    ) -> Result<FareQuote, FareError> {
+        fare_table.quote(zones, profile, group_size, self.stored_value_cents)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::fares::{FareError, FareProduct, FareSchedule, FareScheduleError, FareTable};
+    use crate::history::{History, TransactionType};
+    use crate::models::{CustomerProfile, ZoneSet};
+    use crate::test_cards::{helsinki, CardDump};
+    use chrono::prelude::*;
+
+    const SCHEDULE_TOML: &str = r#"
+        [[tables]]
+        effective_from = "2019-04-27"
+        effective_to = "2019-12-31"
+
+        [[tables.fares]]
+        product = "single"
+        zones = "AB"
+        profile = "adult"
+        price_cents = 280
+
+        [[tables]]
+        effective_from = "2020-01-01"
+
+        [[tables.fares]]
+        product = "single"
+        zones = "AB"
+        profile = "adult"
+        price_cents = 280
+
+        [[tables.fares]]
+        product = "single"
+        zones = "ABC"
+        profile = "adult"
+        price_cents = 420
+
+        [[tables.fares]]
+        product = { period = 30 }
+        zones = "AB"
+        profile = "adult"
+        price_cents = 5970
+    "#;
+
+    fn fare_table() -> FareTable {
+        let mut table = FareTable::new();
+        table.insert("AB".parse().unwrap(), CustomerProfile::Adult, 280);
+        table.insert("BC".parse().unwrap(), CustomerProfile::Adult, 280);
+        table.insert("ABC".parse().unwrap(), CustomerProfile::Adult, 410);
+        table.insert("AB".parse().unwrap(), CustomerProfile::Child, 140);
+        table
+    }
+
+    #[test]
+    fn should_pick_cheapest_covering_fare() {
+        let table = fare_table();
+        let b: ZoneSet = "B".parse().unwrap();
+        assert_eq!(table.price(b, CustomerProfile::Adult, 1), Ok(280));
+        assert_eq!(
+            table.price("AC".parse().unwrap(), CustomerProfile::Adult, 2),
+            Ok(820)
+        );
+        assert_eq!(
+            table.price("D".parse().unwrap(), CustomerProfile::Adult, 1),
+            Err(FareError::NoFare)
+        );
+        assert_eq!(
+            table.price(b, CustomerProfile::Child, 0),
+            Err(FareError::EmptyGroup)
+        );
+        assert_eq!(
+            table.price(ZoneSet::new(), CustomerProfile::Adult, 1),
+            Err(FareError::NoZones)
+        );
+        assert!(table.find(ZoneSet::new(), CustomerProfile::Adult).is_none());
+    }
+
+    #[test]
+    fn should_check_fare_against_stored_value() {
+        let mut dump = CardDump::new();
+        dump.stored_value(500);
+        let card = dump.build();
+        let table = fare_table();
+
+        let quote = card
+            .quote_fare(&table, "ABC".parse().unwrap(), CustomerProfile::Adult, 1)
+            .unwrap();
+        assert!(quote.is_covered);
+        assert_eq!(quote.shortfall_cents, 0);
+
+        let quote = card
+            .quote_fare(&table, "ABC".parse().unwrap(), CustomerProfile::Adult, 2)
+            .unwrap();
+        assert_eq!(quote.price_cents, 820);
+        assert!(!quote.is_covered);
+        assert_eq!(quote.shortfall_cents, 320);
+    }
+
+    #[test]
+    fn should_select_table_by_date() {
+        let schedule = FareSchedule::from_toml(SCHEDULE_TOML).unwrap();
+        let abc: ZoneSet = "ABC".parse().unwrap();
+        let old = schedule
+            .table_for(NaiveDate::from_ymd_opt(2019, 6, 1).unwrap())
+            .unwrap();
+        assert!(old.find(abc, CustomerProfile::Adult).is_none());
+
+        let new = schedule
+            .table_for(NaiveDate::from_ymd_opt(2020, 6, 1).unwrap())
+            .unwrap();
+        assert_eq!(new.price(abc, CustomerProfile::Adult, 1), Ok(420));
+        let period = new
+            .find_product(
+                FareProduct::Period(30),
+                "A".parse().unwrap(),
+                CustomerProfile::Adult,
+            )
+            .unwrap();
+        assert_eq!(period.price_cents, 5970);
+
+        assert!(schedule
+            .table_for(NaiveDate::from_ymd_opt(2019, 1, 1).unwrap())
+            .is_none());
+    }
+
+    #[test]
+    fn should_load_json_schedules() {
+        let json = r#"{ "tables": [{
+            "effective_from": "2020-01-01",
+            "fares": [{ "product": "single", "zones": "BC", "profile": "child", "price_cents": 140 }]
+        }] }"#;
+        let schedule = FareSchedule::from_json(json).unwrap();
+        let table = schedule
+            .table_for(NaiveDate::from_ymd_opt(2030, 1, 1).unwrap())
+            .unwrap();
+        assert_eq!(
+            table.price("C".parse().unwrap(), CustomerProfile::Child, 1),
+            Ok(140)
+        );
+
+        let bad_zone = json.replace("\"BC\"", "\"BX\"");
+        assert!(FareSchedule::from_json(&bad_zone).is_err());
+
+        let inverted = json.replace(
+            "\"effective_from\": \"2020-01-01\"",
+            "\"effective_from\": \"2020-01-01\", \"effective_to\": \"2019-12-31\"",
+        );
+        assert!(matches!(
+            FareSchedule::from_json(&inverted),
+            Err(FareScheduleError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn should_explain_historic_fares() {
+        let schedule = FareSchedule::from_toml(SCHEDULE_TOML).unwrap();
+        let entry = History {
+            transaction_type: TransactionType::ValueTicket,
+            boarding_datetime: helsinki(2020, 3, 2, 8, 15),
+            transfer_end_datetime: helsinki(2020, 3, 2, 9, 35),
+            ticket_fare_cents: 840,
+            group_size: 2,
+            remaining_value: 1000,
+        };
+        let fares = schedule.explain_history_fare(&entry).unwrap();
+        assert_eq!(fares.len(), 1);
+        assert_eq!(fares[0].zones.to_string(), "ABC");
+    }
+
+    #[test]
+    fn should_reject_overflowing_group_price() {
+        let mut table = fare_table();
+        table.insert("D".parse().unwrap(), CustomerProfile::Adult, u32::MAX);
+        let d: ZoneSet = "D".parse().unwrap();
+        assert_eq!(table.price(d, CustomerProfile::Adult, 1), Ok(u32::MAX));
+        assert_eq!(
+            table.price(d, CustomerProfile::Adult, 2),
+            Err(FareError::Overflow)
+        );
+    }
+
+    #[test]
+    fn should_skip_overflowing_fares_when_explaining() {
+        let schedule = FareSchedule::from_toml(
+            r#"
+            [[tables]]
+            effective_from = "2020-01-01"
+
+            [[tables.fares]]
+            product = "single"
+            zones = "AB"
+            profile = "adult"
+            price_cents = 4294967295
+            "#,
+        )
+        .unwrap();
+        let entry = History {
+            transaction_type: TransactionType::ValueTicket,
+            boarding_datetime: helsinki(2020, 3, 2, 8, 15),
+            transfer_end_datetime: helsinki(2020, 3, 2, 9, 35),
+            ticket_fare_cents: 560,
+            group_size: 2,
+            remaining_value: 1000,
+        };
+        assert!(schedule.explain_history_fare(&entry).unwrap().is_empty());
+    }
+}
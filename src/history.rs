@@ -2,7 +2,7 @@ use crate::conversion::*;
 use crate::en1545date::from_en1545_date_and_time;
 use chrono::prelude::*;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct History {
     pub transaction_type: TransactionType,
     pub boarding_datetime: DateTime<Utc>,
@@ -30,7 +30,7 @@ pub fn create_history_entries(history_bytes: &[u8]) -> Vec<History> {
             continue;
         }
         let entry_offset = i * entry_size;
-        let transaction_type = get_bits_as_u8(history_bytes, 0 + entry_offset, 1);
+        let transaction_type = get_bits_as_u8(history_bytes, entry_offset, 1);
         let boarding_date = get_bits_as_u16(history_bytes, 1 + entry_offset, 14);
         let boarding_time = get_bits_as_u16(history_bytes, 15 + entry_offset, 11);
         let end_date = get_bits_as_u16(history_bytes, 26 + entry_offset, 14);
@@ -0,0 +1,253 @@
+//! A long-term record of a card's trips.
+//!
+//! The card only keeps its most recent history entries, so the journal collects the
+//! entries from every read of a card, and stores each one only once.
+
+use crate::history::{History, TransactionType};
+use crate::travelcard::TravelCard;
+use chrono::prelude::*;
+use rusqlite::types::Type;
+use rusqlite::{params, Connection, Row};
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum JournalError {
+    Sqlite(rusqlite::Error),
+}
+
+impl std::fmt::Display for JournalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            JournalError::Sqlite(e) => write!(f, "Trip journal database error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for JournalError {}
+
+impl From<rusqlite::Error> for JournalError {
+    fn from(e: rusqlite::Error) -> Self {
+        JournalError::Sqlite(e)
+    }
+}
+
+/// Trips from every read of every card ingested, stored in an SQLite database.
+pub struct TripJournal {
+    connection: Connection,
+}
+
+// Every column is part of the key, so re-ingesting an entry from a later read is a no-op.
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS history (
+        application_instance_id TEXT NOT NULL,
+        transaction_type INTEGER NOT NULL,
+        boarding_timestamp INTEGER NOT NULL,
+        transfer_end_timestamp INTEGER NOT NULL,
+        ticket_fare_cents INTEGER NOT NULL,
+        group_size INTEGER NOT NULL,
+        remaining_value INTEGER NOT NULL,
+        PRIMARY KEY (application_instance_id, transaction_type, boarding_timestamp,
+            transfer_end_timestamp, ticket_fare_cents, group_size, remaining_value)
+    );
+";
+
+const SELECT_HISTORY: &str = "
+    SELECT transaction_type, boarding_timestamp, transfer_end_timestamp,
+        ticket_fare_cents, group_size, remaining_value
+    FROM history
+";
+
+fn conversion_error(column: usize, message: String) -> rusqlite::Error {
+    rusqlite::Error::FromSqlConversionFailure(column, Type::Integer, message.into())
+}
+
+fn transaction_type_from_row(row: &Row, column: usize) -> rusqlite::Result<TransactionType> {
+    match row.get::<_, u8>(column)? {
+        0 => Ok(TransactionType::SeasonPass),
+        1 => Ok(TransactionType::ValueTicket),
+        other => Err(conversion_error(
+            column,
+            format!("Unknown transaction type {}", other),
+        )),
+    }
+}
+
+fn datetime_from_row(row: &Row, column: usize) -> rusqlite::Result<DateTime<Utc>> {
+    let timestamp: i64 = row.get(column)?;
+    DateTime::from_timestamp(timestamp, 0)
+        .ok_or_else(|| conversion_error(column, format!("Timestamp {} is out of range", timestamp)))
+}
+
+fn history_from_row(row: &Row) -> rusqlite::Result<History> {
+    Ok(History {
+        transaction_type: transaction_type_from_row(row, 0)?,
+        boarding_datetime: datetime_from_row(row, 1)?,
+        transfer_end_datetime: datetime_from_row(row, 2)?,
+        ticket_fare_cents: row.get(3)?,
+        group_size: row.get(4)?,
+        remaining_value: row.get(5)?,
+    })
+}
+
+impl TripJournal {
+    /// Opens the journal stored at the given path, creating it if it doesn't exist.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<TripJournal, JournalError> {
+        TripJournal::from_connection(Connection::open(path)?)
+    }
+
+    /// Opens a journal that only lives as long as the returned value.
+    pub fn open_in_memory() -> Result<TripJournal, JournalError> {
+        TripJournal::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(connection: Connection) -> Result<TripJournal, JournalError> {
+        connection.execute_batch(SCHEMA)?;
+        Ok(TripJournal { connection })
+    }
+
+    /// Adds the history of a card read to the journal. Returns how many entries were new.
+    pub fn ingest(&mut self, card: &TravelCard) -> Result<usize, JournalError> {
+        self.ingest_history(&card.application_instance_id, &card.history)
+    }
+
+    /// Adds history entries read from the card with the given instance ID. Returns how many entries were new.
+    pub fn ingest_history(
+        &mut self,
+        application_instance_id: &str,
+        history: &[History],
+    ) -> Result<usize, JournalError> {
+        let transaction = self.connection.transaction()?;
+        let mut inserted = 0;
+        {
+            let mut insert = transaction
+                .prepare("INSERT OR IGNORE INTO history VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)")?;
+            for entry in history {
+                inserted += insert.execute(params![
+                    application_instance_id,
+                    entry.transaction_type as u32,
+                    entry.boarding_datetime.timestamp(),
+                    entry.transfer_end_datetime.timestamp(),
+                    entry.ticket_fare_cents,
+                    entry.group_size,
+                    entry.remaining_value,
+                ])?;
+            }
+        }
+        transaction.commit()?;
+        Ok(inserted)
+    }
+
+    /// The instance IDs of every card in the journal.
+    pub fn card_ids(&self) -> Result<Vec<String>, JournalError> {
+        let mut query = self.connection.prepare(
+            "SELECT DISTINCT application_instance_id FROM history ORDER BY application_instance_id",
+        )?;
+        let ids = query.query_map([], |row| row.get(0))?;
+        Ok(ids.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Every journaled entry for the given card, oldest first.
+    pub fn entries(&self, application_instance_id: &str) -> Result<Vec<History>, JournalError> {
+        let mut query = self.connection.prepare(&format!(
+            "{} WHERE application_instance_id = ?1 ORDER BY boarding_timestamp, transfer_end_timestamp",
+            SELECT_HISTORY
+        ))?;
+        let entries = query.query_map(params![application_instance_id], history_from_row)?;
+        Ok(entries.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// The journaled entries for the given card boarded at or after `from`, and before `to`, oldest first.
+    pub fn entries_between(
+        &self,
+        application_instance_id: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<History>, JournalError> {
+        let mut query = self.connection.prepare(&format!(
+            "{} WHERE application_instance_id = ?1 AND boarding_timestamp >= ?2 AND boarding_timestamp < ?3
+            ORDER BY boarding_timestamp, transfer_end_timestamp",
+            SELECT_HISTORY
+        ))?;
+        let entries = query.query_map(
+            params![application_instance_id, from.timestamp(), to.timestamp()],
+            history_from_row,
+        )?;
+        Ok(entries.collect::<rusqlite::Result<_>>()?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::history::{History, TransactionType};
+    use crate::journal::TripJournal;
+    use crate::test_cards::{helsinki, trip};
+
+    /// A value ticket trip at 08:00 on the given day of March 2020.
+    fn march_trip(day: u32, remaining_value: u32) -> History {
+        trip(
+            TransactionType::ValueTicket,
+            helsinki(2020, 3, day, 8, 0),
+            280,
+            remaining_value,
+        )
+    }
+
+    #[test]
+    fn should_merge_overlapping_reads() {
+        let mut journal = TripJournal::open_in_memory().unwrap();
+        let first_read = vec![march_trip(2, 720), march_trip(1, 1000)];
+        let second_read = vec![march_trip(3, 440), march_trip(2, 720)];
+
+        assert_eq!(journal.ingest_history("card", &first_read).unwrap(), 2);
+        assert_eq!(journal.ingest_history("card", &second_read).unwrap(), 1);
+        assert_eq!(journal.ingest_history("other", &second_read).unwrap(), 2);
+
+        assert_eq!(
+            journal.entries("card").unwrap(),
+            vec![march_trip(1, 1000), march_trip(2, 720), march_trip(3, 440)]
+        );
+        assert_eq!(journal.card_ids().unwrap(), vec!["card", "other"]);
+    }
+
+    #[test]
+    fn should_query_by_date_range() {
+        let mut journal = TripJournal::open_in_memory().unwrap();
+        journal
+            .ingest_history(
+                "card",
+                &[march_trip(1, 1000), march_trip(2, 720), march_trip(3, 440)],
+            )
+            .unwrap();
+
+        let entries = journal
+            .entries_between(
+                "card",
+                helsinki(2020, 3, 2, 0, 0),
+                helsinki(2020, 3, 3, 8, 0),
+            )
+            .unwrap();
+        assert_eq!(entries, vec![march_trip(2, 720)]);
+    }
+
+    #[test]
+    fn should_reject_corrupt_rows() {
+        let journal = TripJournal::open_in_memory().unwrap();
+        journal
+            .connection
+            .execute(
+                "INSERT INTO history VALUES ('bad type', 7, 0, 0, 280, 1, 1000)",
+                [],
+            )
+            .unwrap();
+        journal
+            .connection
+            .execute(
+                "INSERT INTO history VALUES ('bad time', 1, 9223372036854775807, 0, 280, 1, 1000)",
+                [],
+            )
+            .unwrap();
+
+        assert!(journal.entries("bad type").is_err());
+        assert!(journal.entries("bad time").is_err());
+    }
+}
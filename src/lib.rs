@@ -1,7 +1,22 @@
+pub mod analytics;
+pub mod balance;
+pub mod clock;
+pub mod consistency;
 mod conversion;
 pub mod desfire;
+pub mod diff;
 mod en1545date;
 pub mod eticket;
+pub mod fares;
 pub mod history;
+#[cfg(feature = "journal")]
+pub mod journal;
 pub mod models;
+pub mod reader;
+pub mod recommend;
+pub mod registry;
 pub mod travelcard;
+pub mod validity;
+
+#[cfg(test)]
+mod test_cards;
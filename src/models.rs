@@ -1,3 +1,8 @@
+use crate::en1545date::{helsinki_date, helsinki_midnight};
+use crate::registry::Registry;
+use chrono::prelude::*;
+use chrono::Duration;
+
 #[derive(Debug)]
 
 /// Indicates whether a PeriodPass or Ticket uses the old-style fares and zones, or the new.
@@ -83,8 +88,8 @@ impl From<u8> for BoardingDirection {
 /// Represents an area in which, or a vehicle for which, a ticket is valid.
 #[derive(Debug)]
 pub enum ValidityArea {
-    OldZone(u8),
-    Zone(Vec<ValidityZone>),
+    OldZone(OldZoneArea),
+    Zone(ZoneSet),
     Vehicle(VehicleType),
 }
 
@@ -94,26 +99,157 @@ impl ValidityArea {
     pub const NEW_ZONE_TYPE: u8 = 2; // The docs LIE, and don't include this value. But it's there!
 
     pub(crate) fn new(area_type: u8, area_value: u8) -> ValidityArea {
-        let mut zones: Vec<ValidityZone> = Vec::new();
         match area_type {
-            // TODO: Wrap this a bit more nicely. It represents an old zone (i.e. Zone 1, Zone 2, Region etc)
-            ValidityArea::OLD_ZONE_TYPE => ValidityArea::OldZone(area_value),
+            ValidityArea::OLD_ZONE_TYPE => ValidityArea::OldZone(OldZoneArea::from(area_value)),
             ValidityArea::VEHICLE_TYPE => ValidityArea::Vehicle(VehicleType::from(area_value)),
-            ValidityArea::NEW_ZONE_TYPE => {
-                let from_zone = (area_value & 0b0011_1000) >> 3; // leftmost 3 bits
-                let to_zone = area_value & 0b0000_0111; // 3 bits to the right of that
-                for val in from_zone..=to_zone {
-                    zones.push(ValidityZone::from(val));
-                }
-                ValidityArea::Zone(zones)
-            }
+            ValidityArea::NEW_ZONE_TYPE => ValidityArea::Zone(ZoneSet::from_card_range(area_value)),
             e => panic!("Unsupported area type: {}", e),
         }
     }
+
+    /// The new-style zones this area is valid in. None for vehicle types,
+    /// unknown old-style regions, and empty zone sets.
+    pub fn zones(&self) -> Option<ZoneSet> {
+        let zones = match self {
+            ValidityArea::Zone(zones) => *zones,
+            ValidityArea::OldZone(old_zone) => old_zone.to_new_zones()?,
+            ValidityArea::Vehicle(_) => return None,
+        };
+        if zones.is_empty() {
+            None
+        } else {
+            Some(zones)
+        }
+    }
+
+    /// Whether a boarding in the given area is covered by this validity area.
+    /// Returns None if the two can't be compared, e.g. a zone-based ticket
+    /// against a vehicle-type boarding area, an unknown old-style region, or an empty zone set.
+    pub fn covers(&self, boarding_area: &BoardingArea) -> Option<bool> {
+        if let ValidityArea::Vehicle(vehicle) = self {
+            return match boarding_area {
+                BoardingArea::Vehicle(boarded) => Some(vehicle == boarded),
+                _ => None,
+            };
+        }
+        let valid_zones = self.zones()?;
+        match boarding_area {
+            BoardingArea::Zone(zone) => Some(valid_zones.contains(*zone)),
+            BoardingArea::ZoneCircle(circle) => Some(valid_zones.is_superset(&circle.zones())),
+            BoardingArea::Vehicle(_) => None,
+        }
+    }
+}
+
+/// A pre-2019 HSL region, used by products with the old-style fares and zones.
+/// Unrecognized values are kept as `Unknown`.
+///
+/// The raw codes (0 for Helsinki internal through 7 for Regional 3) are an assumption with
+/// no cited source, and haven't been checked against a real card yet.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum OldZoneArea {
+    HelsinkiInternal,
+    EspooInternal,
+    VantaaInternal,
+    Regional,
+    KeravaSipoo,
+    KirkkonummiSiuntio,
+    Regional2,
+    Regional3,
+    Unknown(u8),
+}
+
+impl OldZoneArea {
+    /// The name of this region in the given language.
+    pub fn name(&self, language: Language) -> &'static str {
+        match (self, language) {
+            (OldZoneArea::HelsinkiInternal, Language::Finnish) => "Helsingin sisäinen",
+            (OldZoneArea::HelsinkiInternal, Language::Swedish) => "Helsingfors interna",
+            (OldZoneArea::HelsinkiInternal, Language::English) => "Helsinki internal",
+            (OldZoneArea::EspooInternal, Language::Finnish) => "Espoon sisäinen",
+            (OldZoneArea::EspooInternal, Language::Swedish) => "Esbo interna",
+            (OldZoneArea::EspooInternal, Language::English) => "Espoo internal",
+            (OldZoneArea::VantaaInternal, Language::Finnish) => "Vantaan sisäinen",
+            (OldZoneArea::VantaaInternal, Language::Swedish) => "Vanda interna",
+            (OldZoneArea::VantaaInternal, Language::English) => "Vantaa internal",
+            (OldZoneArea::Regional, Language::Finnish) => "Seutu",
+            (OldZoneArea::Regional, Language::Swedish) => "Regional",
+            (OldZoneArea::Regional, Language::English) => "Regional",
+            (OldZoneArea::KeravaSipoo, Language::Finnish) => "Kerava–Sipoo",
+            (OldZoneArea::KeravaSipoo, Language::Swedish) => "Kervo–Sibbo",
+            (OldZoneArea::KeravaSipoo, Language::English) => "Kerava–Sipoo",
+            (OldZoneArea::KirkkonummiSiuntio, Language::Finnish) => "Kirkkonummi–Siuntio",
+            (OldZoneArea::KirkkonummiSiuntio, Language::Swedish) => "Kyrkslätt–Sjundeå",
+            (OldZoneArea::KirkkonummiSiuntio, Language::English) => "Kirkkonummi–Siuntio",
+            (OldZoneArea::Regional2, Language::Finnish) => "Lähiseutu 2",
+            (OldZoneArea::Regional2, Language::Swedish) => "Närregion 2",
+            (OldZoneArea::Regional2, Language::English) => "Regional 2",
+            (OldZoneArea::Regional3, Language::Finnish) => "Lähiseutu 3",
+            (OldZoneArea::Regional3, Language::Swedish) => "Närregion 3",
+            (OldZoneArea::Regional3, Language::English) => "Regional 3",
+            (OldZoneArea::Unknown(_), Language::Finnish) => "Tuntematon alue",
+            (OldZoneArea::Unknown(_), Language::Swedish) => "Okänt område",
+            (OldZoneArea::Unknown(_), Language::English) => "Unknown area",
+        }
+    }
+
+    /// The post-2019 zones covering exactly the municipalities of this region, following
+    /// HSL's 2019 zone map: Helsinki is A–B, Espoo and Vantaa are B–C, and Kerava, Kirkkonummi
+    /// and Sipoo are D.
+    ///
+    /// None where no single zone set matches: Kirkkonummi–Siuntio, because Siuntio lies
+    /// outside zones A–D, and Regional 2 and 3, which stand for a pair or triple of
+    /// neighbouring municipalities that the code alone doesn't identify.
+    pub fn to_new_zones(&self) -> Option<ZoneSet> {
+        use ValidityZone::*;
+        let (from, to) = match self {
+            OldZoneArea::HelsinkiInternal => (ZoneA, ZoneB),
+            OldZoneArea::EspooInternal | OldZoneArea::VantaaInternal => (ZoneB, ZoneC),
+            OldZoneArea::Regional => (ZoneA, ZoneD),
+            OldZoneArea::KeravaSipoo => (ZoneD, ZoneD),
+            OldZoneArea::KirkkonummiSiuntio
+            | OldZoneArea::Regional2
+            | OldZoneArea::Regional3
+            | OldZoneArea::Unknown(_) => return None,
+        };
+        Some(ZoneSet::from_range(from, to))
+    }
+}
+
+impl From<u8> for OldZoneArea {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => OldZoneArea::HelsinkiInternal,
+            1 => OldZoneArea::EspooInternal,
+            2 => OldZoneArea::VantaaInternal,
+            3 => OldZoneArea::Regional,
+            4 => OldZoneArea::KeravaSipoo,
+            5 => OldZoneArea::KirkkonummiSiuntio,
+            6 => OldZoneArea::Regional2,
+            7 => OldZoneArea::Regional3,
+            e => OldZoneArea::Unknown(e),
+        }
+    }
+}
+
+impl From<&OldZoneArea> for u8 {
+    fn from(value: &OldZoneArea) -> Self {
+        match value {
+            OldZoneArea::HelsinkiInternal => 0,
+            OldZoneArea::EspooInternal => 1,
+            OldZoneArea::VantaaInternal => 2,
+            OldZoneArea::Regional => 3,
+            OldZoneArea::KeravaSipoo => 4,
+            OldZoneArea::KirkkonummiSiuntio => 5,
+            OldZoneArea::Regional2 => 6,
+            OldZoneArea::Regional3 => 7,
+            OldZoneArea::Unknown(num) => *num,
+        }
+    }
 }
 
 /// The HSL fare zone(s) in which a ticket is valid.
-#[derive(Clone, Debug)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum ValidityZone {
     ZoneA = 0,
     ZoneB = 1,
@@ -156,6 +292,132 @@ impl From<&ValidityZone> for u8 {
     }
 }
 
+impl ValidityZone {
+    /// The letter HSL uses for this zone, e.g. 'A'.
+    pub fn letter(&self) -> char {
+        (b'A' + u8::from(self)) as char
+    }
+
+    /// The zone with the given letter, if any. Case-insensitive.
+    pub fn from_letter(letter: char) -> Option<ValidityZone> {
+        match letter.to_ascii_uppercase() {
+            l @ 'A'..='H' => Some(ValidityZone::from(l as u8 - b'A')),
+            _ => None,
+        }
+    }
+}
+
+/// A set of HSL fare zones, stored as a bitmask where bit N is set if zone N (A = 0) is in the set.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct ZoneSet(u8);
+
+impl ZoneSet {
+    pub fn new() -> ZoneSet {
+        ZoneSet(0)
+    }
+
+    pub fn from_bits(bits: u8) -> ZoneSet {
+        ZoneSet(bits)
+    }
+
+    pub fn bits(&self) -> u8 {
+        self.0
+    }
+
+    /// All zones from `from` to `to`, inclusive. The zones are concentric rings, so a range
+    /// given outermost first, e.g. D to B, covers the same zones as B to D.
+    pub fn from_range(from: ValidityZone, to: ValidityZone) -> ZoneSet {
+        let (from, to) = (u8::from(&from), u8::from(&to));
+        (from.min(to)..=from.max(to))
+            .map(ValidityZone::from)
+            .collect()
+    }
+
+    /// Decodes the on-card zone range: the from zone in the upper 3 bits of the 6-bit value,
+    /// and the to zone in the lower 3 bits.
+    pub fn from_card_range(area_value: u8) -> ZoneSet {
+        let from_zone = (area_value & 0b0011_1000) >> 3; // leftmost 3 bits
+        let to_zone = area_value & 0b0000_0111; // 3 bits to the right of that
+        ZoneSet::from_range(ValidityZone::from(from_zone), ValidityZone::from(to_zone))
+    }
+
+    pub fn insert(&mut self, zone: ValidityZone) {
+        self.0 |= 1 << u8::from(&zone);
+    }
+
+    pub fn contains(&self, zone: ValidityZone) -> bool {
+        self.0 & (1 << u8::from(&zone)) != 0
+    }
+
+    /// True if every zone in `other` is also in this set.
+    pub fn is_superset(&self, other: &ZoneSet) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn union(&self, other: &ZoneSet) -> ZoneSet {
+        ZoneSet(self.0 | other.0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    /// The zones in this set, innermost first.
+    pub fn iter(&self) -> impl Iterator<Item = ValidityZone> {
+        let bits = self.0;
+        (0..8u8)
+            .filter(move |i| bits & (1 << i) != 0)
+            .map(ValidityZone::from)
+    }
+}
+
+impl std::iter::FromIterator<ValidityZone> for ZoneSet {
+    fn from_iter<I: IntoIterator<Item = ValidityZone>>(iter: I) -> Self {
+        let mut set = ZoneSet::new();
+        for zone in iter {
+            set.insert(zone);
+        }
+        set
+    }
+}
+
+/// Formats the set in HSL notation, e.g. "ABC".
+impl std::fmt::Display for ZoneSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for zone in self.iter() {
+            write!(f, "{}", zone.letter())?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses HSL notation, e.g. "ABC" or "d".
+impl std::str::FromStr for ZoneSet {
+    type Err = ParseZoneSetError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.chars()
+            .map(|c| ValidityZone::from_letter(c).ok_or(ParseZoneSetError(c)))
+            .collect()
+    }
+}
+
+/// Returned when a string contains something other than zone letters.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ParseZoneSetError(pub char);
+
+impl std::fmt::Display for ParseZoneSetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "'{}' is not a fare zone.", self.0)
+    }
+}
+
+impl std::error::Error for ParseZoneSetError {}
+
 #[derive(Debug)]
 pub enum ValidityLength {
     Minutes(u8),
@@ -174,6 +436,21 @@ impl ValidityLength {
             e => panic!("Given value ({}) for ValidityLength type not supported.", e),
         }
     }
+
+    /// The instant a product with this validity length, starting at `start`, stops being valid.
+    /// `Days` are Helsinki calendar days, counting the start day as the first.
+    pub fn end_from(&self, start: DateTime<Utc>) -> DateTime<Utc> {
+        match self {
+            ValidityLength::Minutes(num) => start + Duration::minutes(i64::from(*num)),
+            ValidityLength::Hours(num) => start + Duration::hours(i64::from(*num)),
+            ValidityLength::TwentyFourHourPeriods(num) => {
+                start + Duration::hours(24 * i64::from(*num))
+            }
+            ValidityLength::Days(num) => {
+                helsinki_midnight(helsinki_date(start) + Duration::days(i64::from(*num)))
+            }
+        }
+    }
 }
 
 impl From<&ValidityLength> for u8 {
@@ -188,7 +465,7 @@ impl From<&ValidityLength> for u8 {
 }
 
 /// The vehicle type on which this ticket is valid.
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum VehicleType {
     Undefined = 0,
     Bus = 1,
@@ -274,6 +551,25 @@ impl SaleDevice {
             e => panic!("Given value ({}) for SaleDeviceType not supported.", e),
         }
     }
+
+    /// The raw device type value, as stored on the card.
+    pub fn device_type(&self) -> u8 {
+        match self {
+            SaleDevice::ServicePointSalesDevice(_) => 0,
+            SaleDevice::DriverTicketMachine(_) => 1,
+            SaleDevice::CardReader(_) => 2,
+            SaleDevice::TicketMachine(_) => 3,
+            SaleDevice::Server(_) => 4,
+            SaleDevice::HSLSmallEquipment(_) => 5,
+            SaleDevice::ExternalServiceEquipment(_) => 6,
+            SaleDevice::Reserved(_) => 7,
+        }
+    }
+
+    /// A human-readable description of this device, resolved through the given registry.
+    pub fn description(&self, registry: &Registry) -> String {
+        registry.describe_sale_device(self)
+    }
 }
 
 impl From<&SaleDevice> for u16 {
@@ -296,15 +592,15 @@ impl From<&SaleDevice> for u16 {
 pub enum BoardingArea {
     Zone(ValidityZone),
     Vehicle(VehicleType),
-    ZoneCircle(u8), // Not sure what this is. One of the old-style regions?
+    ZoneCircle(ZoneCircle),
 }
 
 impl BoardingArea {
     pub(crate) fn new(area_type: u8, area_value: u8) -> BoardingArea {
         match area_type {
             0 => BoardingArea::Zone(ValidityZone::from(area_value)),
-            1 => BoardingArea::Vehicle(VehicleType::from(area_type)),
-            2 => BoardingArea::ZoneCircle(area_value),
+            1 => BoardingArea::Vehicle(VehicleType::from(area_value)),
+            2 => BoardingArea::ZoneCircle(ZoneCircle::from(area_value)),
             e => panic!("Given value ({}) for BoardingArea type not supported.", e),
         }
     }
@@ -315,7 +611,297 @@ impl From<&BoardingArea> for u8 {
         match val {
             BoardingArea::Zone(zone) => u8::from(zone),
             BoardingArea::Vehicle(vehicle_type) => u8::from(vehicle_type),
-            BoardingArea::ZoneCircle(zone_value) => *zone_value,
+            BoardingArea::ZoneCircle(circle) => u8::from(circle),
+        }
+    }
+}
+
+/// A contiguous run of HSL fare zones, e.g. A through C.
+/// Since the zones are concentric rings, this describes everything between the two rings.
+///
+/// The encoding is an assumption with no cited source. It's decoded the same way as a
+/// new-style `ValidityArea`, with the from zone in the upper 3 bits and the to zone in the
+/// lower 3 bits, and hasn't been checked against a real card yet.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct ZoneCircle {
+    pub from: ValidityZone,
+    pub to: ValidityZone,
+}
+
+impl ZoneCircle {
+    /// All zones that are part of this circle. Never empty: a circle stored outermost
+    /// zone first covers the same zones as one stored innermost first.
+    pub fn zones(&self) -> ZoneSet {
+        ZoneSet::from_range(self.from, self.to)
+    }
+
+    pub fn contains(&self, zone: ValidityZone) -> bool {
+        self.zones().contains(zone)
+    }
+}
+
+impl From<u8> for ZoneCircle {
+    fn from(value: u8) -> Self {
+        ZoneCircle {
+            from: ValidityZone::from((value & 0b0011_1000) >> 3), // leftmost 3 bits
+            to: ValidityZone::from(value & 0b0000_0111),          // 3 bits to the right of that
         }
     }
 }
+
+impl From<&ZoneCircle> for u8 {
+    fn from(value: &ZoneCircle) -> Self {
+        (u8::from(&value.from) << 3) | u8::from(&value.to)
+    }
+}
+
+/// The customer group a ticket was sold for. Values come from HSL's customer profile table;
+/// anything not listed there is kept as `Unknown` with its raw value.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum CustomerProfile {
+    Adult,
+    Child,
+    Student,
+    Senior,
+    ReducedMobility,
+    Conscript,
+    Youth,
+    Unknown(u8),
+}
+
+impl CustomerProfile {
+    /// The name of this customer profile in the given language.
+    pub fn name(&self, language: Language) -> &'static str {
+        match (self, language) {
+            (CustomerProfile::Adult, Language::Finnish) => "Aikuinen",
+            (CustomerProfile::Adult, Language::Swedish) => "Vuxen",
+            (CustomerProfile::Adult, Language::English) => "Adult",
+            (CustomerProfile::Child, Language::Finnish) => "Lapsi",
+            (CustomerProfile::Child, Language::Swedish) => "Barn",
+            (CustomerProfile::Child, Language::English) => "Child",
+            (CustomerProfile::Student, Language::Finnish) => "Opiskelija",
+            (CustomerProfile::Student, Language::Swedish) => "Studerande",
+            (CustomerProfile::Student, Language::English) => "Student",
+            (CustomerProfile::Senior, Language::Finnish) => "Eläkeläinen",
+            (CustomerProfile::Senior, Language::Swedish) => "Pensionär",
+            (CustomerProfile::Senior, Language::English) => "Senior",
+            (CustomerProfile::ReducedMobility, Language::Finnish) => "Liikuntarajoitteinen",
+            (CustomerProfile::ReducedMobility, Language::Swedish) => "Rörelsehindrad",
+            (CustomerProfile::ReducedMobility, Language::English) => "Reduced mobility",
+            (CustomerProfile::Conscript, Language::Finnish) => "Varusmies",
+            (CustomerProfile::Conscript, Language::Swedish) => "Beväring",
+            (CustomerProfile::Conscript, Language::English) => "Conscript",
+            (CustomerProfile::Youth, Language::Finnish) => "Nuori",
+            (CustomerProfile::Youth, Language::Swedish) => "Ungdom",
+            (CustomerProfile::Youth, Language::English) => "Youth",
+            (CustomerProfile::Unknown(_), Language::Finnish) => "Tuntematon",
+            (CustomerProfile::Unknown(_), Language::Swedish) => "Okänd",
+            (CustomerProfile::Unknown(_), Language::English) => "Unknown",
+        }
+    }
+
+    /// True if this profile travels at a discounted price compared to an adult.
+    /// Unknown profiles are never assumed to be discounted.
+    pub fn is_discounted(&self) -> bool {
+        !matches!(self, CustomerProfile::Adult | CustomerProfile::Unknown(_))
+    }
+}
+
+/// Parses a customer profile from its snake_case English name, e.g. "reduced_mobility",
+/// or from its raw numeric value.
+impl std::str::FromStr for CustomerProfile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "adult" => Ok(CustomerProfile::Adult),
+            "child" => Ok(CustomerProfile::Child),
+            "student" => Ok(CustomerProfile::Student),
+            "senior" => Ok(CustomerProfile::Senior),
+            "reduced_mobility" => Ok(CustomerProfile::ReducedMobility),
+            "conscript" => Ok(CustomerProfile::Conscript),
+            "youth" => Ok(CustomerProfile::Youth),
+            other => other
+                .parse::<u8>()
+                .map(CustomerProfile::from)
+                .map_err(|_| format!("'{}' is not a customer profile.", other)),
+        }
+    }
+}
+
+impl From<u8> for CustomerProfile {
+    fn from(value: u8) -> Self {
+        match value {
+            1 => CustomerProfile::Adult,
+            2 => CustomerProfile::Child,
+            3 => CustomerProfile::Student,
+            4 => CustomerProfile::Senior,
+            5 => CustomerProfile::ReducedMobility,
+            6 => CustomerProfile::Conscript,
+            7 => CustomerProfile::Youth,
+            e => CustomerProfile::Unknown(e),
+        }
+    }
+}
+
+impl From<&CustomerProfile> for u8 {
+    fn from(value: &CustomerProfile) -> Self {
+        match value {
+            CustomerProfile::Adult => 1,
+            CustomerProfile::Child => 2,
+            CustomerProfile::Student => 3,
+            CustomerProfile::Senior => 4,
+            CustomerProfile::ReducedMobility => 5,
+            CustomerProfile::Conscript => 6,
+            CustomerProfile::Youth => 7,
+            CustomerProfile::Unknown(num) => *num,
+        }
+    }
+}
+
+/// The customer profile of a ticket, and whether it was sold for a single traveller or a group.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum TicketCustomerProfile {
+    Single(CustomerProfile), // CustomerProfile field
+    Group(CustomerProfile),  // CustomerProfileGroup field
+}
+
+impl TicketCustomerProfile {
+    pub(crate) fn new(single_value: u8, group_value: u8) -> TicketCustomerProfile {
+        if group_value > 0 {
+            TicketCustomerProfile::Group(CustomerProfile::from(group_value))
+        } else {
+            TicketCustomerProfile::Single(CustomerProfile::from(single_value))
+        }
+    }
+
+    /// The customer profile, regardless of whether it applies to a single traveller or a group.
+    pub fn profile(&self) -> CustomerProfile {
+        match self {
+            TicketCustomerProfile::Single(profile) | TicketCustomerProfile::Group(profile) => {
+                *profile
+            }
+        }
+    }
+
+    pub fn is_group(&self) -> bool {
+        matches!(self, TicketCustomerProfile::Group(_))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::models::{
+        BoardingArea, CustomerProfile, Language, OldZoneArea, ParseZoneSetError,
+        TicketCustomerProfile, ValidityArea, ValidityZone, ZoneCircle, ZoneSet,
+    };
+
+    #[test]
+    fn customer_profile_should_keep_unknown_raw_values() {
+        let profile = CustomerProfile::from(29);
+        assert_eq!(profile, CustomerProfile::Unknown(29));
+        assert_eq!(u8::from(&profile), 29);
+        assert_eq!(profile.name(Language::English), "Unknown");
+        assert!(!profile.is_discounted());
+    }
+
+    #[test]
+    fn ticket_customer_profile_should_prefer_group_value() {
+        let single = TicketCustomerProfile::new(2, 0);
+        assert_eq!(
+            single,
+            TicketCustomerProfile::Single(CustomerProfile::Child)
+        );
+        assert!(!single.is_group());
+
+        let group = TicketCustomerProfile::new(2, 1);
+        assert_eq!(group, TicketCustomerProfile::Group(CustomerProfile::Adult));
+        assert!(group.is_group());
+        assert_eq!(group.profile(), CustomerProfile::Adult);
+    }
+
+    #[test]
+    fn old_zone_should_decode_to_named_area() {
+        match ValidityArea::new(ValidityArea::OLD_ZONE_TYPE, 3) {
+            ValidityArea::OldZone(area) => {
+                assert_eq!(area, OldZoneArea::Regional);
+                assert_eq!(area.to_new_zones(), Some("ABCD".parse().unwrap()));
+            }
+            other => panic!("Expected an OldZone, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn old_zone_should_map_to_matching_new_zones() {
+        let expected = [
+            (OldZoneArea::HelsinkiInternal, Some("AB")),
+            (OldZoneArea::EspooInternal, Some("BC")),
+            (OldZoneArea::VantaaInternal, Some("BC")),
+            (OldZoneArea::Regional, Some("ABCD")),
+            (OldZoneArea::KeravaSipoo, Some("D")),
+            (OldZoneArea::KirkkonummiSiuntio, None),
+            (OldZoneArea::Regional2, None),
+            (OldZoneArea::Regional3, None),
+        ];
+        for (area, zones) in expected.iter() {
+            let zones: Option<ZoneSet> = zones.map(|zones| zones.parse().unwrap());
+            assert_eq!(area.to_new_zones(), zones, "{:?}", area);
+        }
+    }
+
+    #[test]
+    fn old_zone_should_keep_unknown_raw_values() {
+        let area = OldZoneArea::from(42);
+        assert_eq!(area, OldZoneArea::Unknown(42));
+        assert_eq!(u8::from(&area), 42);
+        assert_eq!(area.to_new_zones(), None);
+    }
+
+    #[test]
+    fn zone_set_should_decode_card_range() {
+        let zones = ZoneSet::from_card_range(0b001_011); // B to D
+        assert_eq!(zones.to_string(), "BCD");
+        assert_eq!(zones.bits(), 0b0000_1110);
+        assert_eq!(zones.len(), 3);
+        assert!(zones.contains(ValidityZone::ZoneC));
+        assert!(!zones.contains(ValidityZone::ZoneA));
+    }
+
+    #[test]
+    fn zone_set_should_support_set_operations() {
+        let ab: ZoneSet = "AB".parse().unwrap();
+        let bc: ZoneSet = "bc".parse().unwrap();
+        let abc = ab.union(&bc);
+        assert_eq!(abc.to_string(), "ABC");
+        assert!(abc.is_superset(&ab));
+        assert!(!ab.is_superset(&bc));
+        assert_eq!(
+            abc.iter().collect::<Vec<_>>(),
+            vec![
+                ValidityZone::ZoneA,
+                ValidityZone::ZoneB,
+                ValidityZone::ZoneC
+            ]
+        );
+        assert_eq!("AX".parse::<ZoneSet>(), Err(ParseZoneSetError('X')));
+    }
+
+    #[test]
+    fn inverted_ranges_should_be_normalized() {
+        assert_eq!(ZoneSet::from_card_range(0b011_001).to_string(), "BCD"); // D to B
+        let circle = ZoneCircle::from(0b011_001);
+        assert_eq!(circle.zones().to_string(), "BCD");
+        assert!(circle.contains(ValidityZone::ZoneC));
+        assert_eq!(u8::from(&circle), 0b011_001);
+    }
+
+    #[test]
+    fn empty_validity_area_should_not_cover_anything() {
+        let empty = ValidityArea::Zone(ZoneSet::new());
+        assert_eq!(empty.covers(&BoardingArea::Zone(ValidityZone::ZoneA)), None);
+        assert_eq!(
+            empty.covers(&BoardingArea::ZoneCircle(ZoneCircle::from(0b000_001))),
+            None
+        );
+    }
+}
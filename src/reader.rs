@@ -0,0 +1,629 @@
+//! Reading a whole travel card through a `CardTransport`: selecting the HSL application,
+//! reading each of its files, and decoding the result.
+
+use crate::desfire::files::{FileSettings, FileSettingsError, FileSize, FileType};
+use crate::desfire::status::{DesfireStatus, StatusError};
+use crate::desfire::{
+    transceive_chained, CardTransport, Command, TransceiveError, APP_INFO_FILE_ID,
+    CONTROL_INFO_FILE_ID, E_TICKET_FILE_ID, HISTORY_FILE_ID, MAX_FRAME_DATA_LENGTH,
+    PERIOD_PASS_FILE_ID, SELECT_HSL_COMMAND, STORED_VALUE_FILE_ID,
+};
+use crate::eticket::{create_e_ticket, ETicket};
+use crate::history::{create_history_entries, History};
+use crate::travelcard::{
+    create_travel_card, read_application_info, read_period_pass, read_stored_value, PeriodPass,
+    TravelCard,
+};
+
+/// How hard to try before giving up on a command. Phones in particular lose the card
+/// whenever it moves, and a second attempt often succeeds.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// How many times to send each command, including the first. 0 is treated as 1.
+    pub max_attempts: u32,
+    /// Whether to select the HSL application again before retrying. Cards that lost power
+    /// forget which application was selected. Reading stops with `ReadError::CardChanged`
+    /// if the card that comes back isn't the one that was being read.
+    pub reselect_on_transport_error: bool,
+}
+
+impl RetryPolicy {
+    /// Send each command once, and give up on the first error.
+    pub const NONE: RetryPolicy = RetryPolicy {
+        max_attempts: 1,
+        reselect_on_transport_error: false,
+    };
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            reselect_on_transport_error: true,
+        }
+    }
+}
+
+/// The files of the HSL application, in the order they're read.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum CardFile {
+    AppInfo,
+    ControlInfo,
+    PeriodPass,
+    StoredValue,
+    ETicket,
+    History,
+}
+
+impl CardFile {
+    pub const ALL: [CardFile; 6] = [
+        CardFile::AppInfo,
+        CardFile::ControlInfo,
+        CardFile::PeriodPass,
+        CardFile::StoredValue,
+        CardFile::ETicket,
+        CardFile::History,
+    ];
+
+    pub fn file_id(self) -> u8 {
+        match self {
+            CardFile::AppInfo => APP_INFO_FILE_ID,
+            CardFile::ControlInfo => CONTROL_INFO_FILE_ID,
+            CardFile::PeriodPass => PERIOD_PASS_FILE_ID,
+            CardFile::StoredValue => STORED_VALUE_FILE_ID,
+            CardFile::ETicket => E_TICKET_FILE_ID,
+            CardFile::History => HISTORY_FILE_ID,
+        }
+    }
+
+    /// The fewest bytes the decoder needs from this file. Larger files are fine, since
+    /// only the start of each is decoded.
+    fn decoded_length(self) -> usize {
+        match self {
+            CardFile::AppInfo => 11,
+            CardFile::ControlInfo => 10,
+            CardFile::PeriodPass => 35,
+            CardFile::StoredValue => 12,
+            CardFile::ETicket => 45,
+            CardFile::History => 0,
+        }
+    }
+}
+
+/// A file that couldn't be read, even after retrying.
+#[derive(Debug)]
+pub struct FileFailure<E> {
+    pub file: CardFile,
+    pub error: ReadError<E>,
+}
+
+impl<E: std::fmt::Display> std::fmt::Display for FileFailure<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}: {}", self.file, self.error)
+    }
+}
+
+/// What was read from a card before one or more of its files failed. Keeps the raw
+/// data of every file that was read, so it isn't lost along with the rest, and decodes
+/// whatever can be decoded from it.
+#[derive(Debug)]
+pub struct PartialTravelCard<E> {
+    /// The data of each file that was read, without status words.
+    pub files: Vec<(CardFile, Vec<u8>)>,
+    pub failures: Vec<FileFailure<E>>,
+}
+
+impl<E> PartialTravelCard<E> {
+    pub fn read_files(&self) -> Vec<CardFile> {
+        self.files.iter().map(|(file, _)| *file).collect()
+    }
+
+    pub fn failed_files(&self) -> Vec<CardFile> {
+        self.failures.iter().map(|failure| failure.file).collect()
+    }
+
+    /// The data of the given file, if it was read.
+    pub fn data(&self, file: CardFile) -> Option<&[u8]> {
+        self.files
+            .iter()
+            .find(|(f, _)| *f == file)
+            .map(|(_, data)| data.as_slice())
+    }
+
+    /// The card's unique ID, if the app info file was read.
+    pub fn application_instance_id(&self) -> Option<String> {
+        self.data(CardFile::AppInfo)
+            .map(|data| read_application_info(data).2)
+    }
+
+    pub fn period_pass(&self) -> Option<PeriodPass> {
+        self.data(CardFile::PeriodPass).map(read_period_pass)
+    }
+
+    pub fn stored_value_cents(&self) -> Option<u32> {
+        self.data(CardFile::StoredValue)
+            .map(|data| read_stored_value(data).cents)
+    }
+
+    pub fn e_ticket(&self) -> Option<ETicket> {
+        self.data(CardFile::ETicket).map(create_e_ticket)
+    }
+
+    pub fn history(&self) -> Option<Vec<History>> {
+        self.data(CardFile::History).map(create_history_entries)
+    }
+}
+
+impl<E: std::fmt::Display> std::fmt::Display for PartialTravelCard<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "Read {} of {} files. Failed to read",
+            self.files.len(),
+            CardFile::ALL.len()
+        )?;
+        for (i, failure) in self.failures.iter().enumerate() {
+            let separator = if i == 0 { " " } else { ", " };
+            write!(f, "{}{}", separator, failure)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum ReadError<E> {
+    /// The card doesn't have the HSL application.
+    NotHslCard,
+    Transceive(TransceiveError<E>),
+    /// The card returned less data than its file settings announced, or than the file's
+    /// decoder needs.
+    FileTooShort {
+        file_id: u8,
+        length: usize,
+    },
+    /// The card's GetFileSettings response couldn't be parsed.
+    Settings(FileSettingsError),
+    /// The card says the file is of a type that can't be read as bytes.
+    NotReadable(FileType),
+    /// The card was replaced by another one partway through reading it.
+    CardChanged,
+    /// Some of the card's files couldn't be read.
+    Partial(PartialTravelCard<E>),
+}
+
+impl<E: std::fmt::Display> std::fmt::Display for ReadError<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ReadError::NotHslCard => write!(f, "Not an HSL card."),
+            ReadError::CardChanged => {
+                write!(f, "The card was swapped for another one while reading it.")
+            }
+            ReadError::Transceive(e) => write!(f, "{}", e),
+            ReadError::FileTooShort { file_id, length } => {
+                write!(f, "File {:#04X} was only {} bytes long.", file_id, length)
+            }
+            ReadError::Settings(e) => write!(f, "{}", e),
+            ReadError::NotReadable(t) => write!(f, "{:?} files can't be read as bytes.", t),
+            ReadError::Partial(partial) => write!(f, "{}", partial),
+        }
+    }
+}
+
+impl<E: std::fmt::Debug + std::fmt::Display> std::error::Error for ReadError<E> {}
+
+impl<E> From<TransceiveError<E>> for ReadError<E> {
+    fn from(e: TransceiveError<E>) -> Self {
+        ReadError::Transceive(e)
+    }
+}
+
+impl<E> From<FileSettingsError> for ReadError<E> {
+    fn from(e: FileSettingsError) -> Self {
+        ReadError::Settings(e)
+    }
+}
+
+struct Session<'a, T: CardTransport + ?Sized> {
+    transport: &'a mut T,
+    policy: RetryPolicy,
+    /// The app info file as first read, to recognize the card again after re-selecting.
+    app_info: Option<Vec<u8>>,
+}
+
+impl<T: CardTransport + ?Sized> Session<'_, T> {
+    /// Sends a command, and all of its READ_NEXT follow-ups, retrying the whole exchange
+    /// from the start when the transport fails. Errors from the card itself aren't retried.
+    fn send(
+        &mut self,
+        command: &[u8],
+        max_frames: usize,
+        reselect: bool,
+    ) -> Result<Vec<u8>, ReadError<T::Error>> {
+        let mut attempt = 1;
+        let mut result = transceive_chained(self.transport, command, max_frames);
+        loop {
+            match result {
+                Err(TransceiveError::Transport(_)) if attempt < self.policy.max_attempts => {}
+                result => return Ok(result?),
+            }
+            attempt += 1;
+            if reselect && self.policy.reselect_on_transport_error {
+                match self.reselect() {
+                    Ok(()) => {}
+                    // The card is still out of reach. Counts as a failed attempt.
+                    Err(ReadError::Transceive(e @ TransceiveError::Transport(_))) => {
+                        result = Err(e);
+                        continue;
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            result = transceive_chained(self.transport, command, max_frames);
+        }
+    }
+
+    /// Selects the HSL application again, and checks that it's still the same card
+    /// by comparing its app info file with the one read earlier.
+    fn reselect(&mut self) -> Result<(), ReadError<T::Error>> {
+        match transceive_chained(self.transport, &SELECT_HSL_COMMAND, 1) {
+            Err(TransceiveError::Status(StatusError(DesfireStatus::ApplicationNotFound))) => {
+                return Err(ReadError::CardChanged)
+            }
+            result => result?,
+        };
+        if let Some(expected) = &self.app_info {
+            let command = Command::read_data(APP_INFO_FILE_ID, 0, expected.len() as u32);
+            let max_frames = expected.len().div_ceil(MAX_FRAME_DATA_LENGTH).max(1);
+            let app_info = transceive_chained(self.transport, &command, max_frames)?;
+            if app_info != *expected {
+                return Err(ReadError::CardChanged);
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads a whole file, sized by what the card's GetFileSettings says about it.
+    fn read_file(&mut self, file: CardFile) -> Result<Vec<u8>, ReadError<T::Error>> {
+        let file_id = file.file_id();
+        let settings_data = self.send(&Command::get_file_settings(file_id), 1, true)?;
+        let settings = FileSettings::parse(&settings_data)?;
+        let command = settings
+            .read_command(file_id)
+            .ok_or(ReadError::NotReadable(settings.file_type))?;
+        // Reading a record file with no records, e.g. a new card's history, fails with a
+        // boundary error, so don't.
+        if let FileSize::Record {
+            current_records: 0, ..
+        } = settings.size
+        {
+            return Ok(vec![]);
+        }
+
+        // Only None for files too large to count in a u32, which can't be read either.
+        let length = settings
+            .read_length()
+            .ok_or(ReadError::NotReadable(settings.file_type))? as usize;
+        let max_frames = length.div_ceil(MAX_FRAME_DATA_LENGTH).max(1);
+        let data = self.send(&command, max_frames, true)?;
+        if data.len() < length.max(file.decoded_length()) {
+            return Err(ReadError::FileTooShort {
+                file_id,
+                length: data.len(),
+            });
+        }
+        Ok(data)
+    }
+}
+
+/// Selects the HSL application on the card, reads all of its files, and decodes them,
+/// retrying with the default `RetryPolicy`.
+pub fn read_travel_card<T: CardTransport + ?Sized>(
+    transport: &mut T,
+) -> Result<TravelCard, ReadError<T::Error>> {
+    read_travel_card_with_policy(transport, RetryPolicy::default())
+}
+
+/// Selects the HSL application on the card, reads all of its files, and decodes them.
+/// Files that still fail after retrying don't stop the others from being read. If any
+/// failed, returns `ReadError::Partial` with everything that was read.
+pub fn read_travel_card_with_policy<T: CardTransport + ?Sized>(
+    transport: &mut T,
+    policy: RetryPolicy,
+) -> Result<TravelCard, ReadError<T::Error>> {
+    let mut session = Session {
+        transport,
+        policy,
+        app_info: None,
+    };
+    match session.send(&SELECT_HSL_COMMAND, 1, false) {
+        Err(ReadError::Transceive(TransceiveError::Status(StatusError(
+            DesfireStatus::ApplicationNotFound,
+        )))) => return Err(ReadError::NotHslCard),
+        result => result?,
+    };
+
+    let mut partial = PartialTravelCard {
+        files: vec![],
+        failures: vec![],
+    };
+    for file in CardFile::ALL.iter().copied() {
+        match session.read_file(file) {
+            Ok(data) => {
+                if file == CardFile::AppInfo {
+                    session.app_info = Some(data.clone());
+                }
+                partial.files.push((file, data));
+            }
+            // Nothing read from here on could be trusted to come from the same card.
+            Err(ReadError::CardChanged) => return Err(ReadError::CardChanged),
+            Err(error) => partial.failures.push(FileFailure { file, error }),
+        }
+    }
+    if !partial.failures.is_empty() {
+        return Err(ReadError::Partial(partial));
+    }
+
+    let data = |file| partial.data(file).unwrap_or_default();
+    Ok(create_travel_card(
+        data(CardFile::AppInfo),
+        data(CardFile::ControlInfo),
+        data(CardFile::PeriodPass),
+        data(CardFile::StoredValue),
+        data(CardFile::ETicket),
+        data(CardFile::History),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::desfire::{
+        Command, HISTORY_FILE_ID, READ_APP_INFO_COMMAND, READ_NEXT_COMMAND, SELECT_HSL_COMMAND,
+    };
+    use crate::reader::{
+        read_travel_card, read_travel_card_with_policy, CardFile, ReadError, RetryPolicy,
+    };
+
+    fn frame(data: Vec<u8>, sw: [u8; 2]) -> Result<Vec<u8>, ()> {
+        let mut response = data;
+        response.extend_from_slice(&sw);
+        Ok(response)
+    }
+
+    /// GetFileSettings data for a freely readable data file of the given size.
+    fn data_file_settings(size: u8) -> Vec<u8> {
+        vec![0x00, 0x00, 0xE0, 0xEE, size, 0x00, 0x00]
+    }
+
+    /// GetFileSettings data for a freely readable file of 12-byte records.
+    fn record_file_settings(max_records: u8, current_records: u8) -> Vec<u8> {
+        vec![
+            0x04,
+            0x00,
+            0xE0,
+            0xEE,
+            12,
+            0x00,
+            0x00,
+            max_records,
+            0x00,
+            0x00,
+            current_records,
+            0x00,
+            0x00,
+        ]
+    }
+
+    /// Answers each command the way a card with the usual file sizes and five history
+    /// records, sent in two frames, would.
+    fn fake_card(command: &[u8]) -> Result<Vec<u8>, ()> {
+        match command[1] {
+            0x5A => frame(vec![], [0x91, 0x00]),
+            0xF5 => {
+                let settings = match command[5] {
+                    0x08 => data_file_settings(11),
+                    0x00 => data_file_settings(10),
+                    0x01 => data_file_settings(35),
+                    0x02 => data_file_settings(12),
+                    0x03 => data_file_settings(45),
+                    0x04 => record_file_settings(14, 5),
+                    _ => return frame(vec![], [0x91, 0xF0]),
+                };
+                frame(settings, [0x91, 0x00])
+            }
+            0xBD => frame(vec![0; usize::from(command[9])], [0x91, 0x00]),
+            0xBB => frame(vec![0x40; 48], [0x91, 0xAF]),
+            0xAF => frame(vec![0x40; 12], [0x91, 0x00]),
+            _ => Err(()),
+        }
+    }
+
+    #[test]
+    fn should_read_whole_card() {
+        let mut sent = vec![];
+        let card = read_travel_card(&mut |command: &[u8]| {
+            sent.push(command.to_vec());
+            fake_card(command)
+        })
+        .unwrap();
+
+        // Select, then the settings and data of each file, then the second history frame.
+        assert_eq!(sent.len(), 14);
+        assert_eq!(sent[0], SELECT_HSL_COMMAND);
+        assert_eq!(sent[1], Command::get_file_settings(0x08));
+        assert_eq!(sent[2], READ_APP_INFO_COMMAND);
+        assert_eq!(sent[12][5], HISTORY_FILE_ID);
+        assert_eq!(sent[13], READ_NEXT_COMMAND);
+        assert_eq!(card.history.len(), 5);
+    }
+
+    #[test]
+    fn should_size_reads_from_file_settings() {
+        let history = vec![0x40; 20 * 12];
+        let mut sent = vec![];
+        let mut offset = 0;
+        let card = read_travel_card(&mut |command: &[u8]| {
+            sent.push(command.to_vec());
+            match (command[1], command.get(5)) {
+                (0xF5, Some(0x01)) => frame(data_file_settings(40), [0x91, 0x00]),
+                (0xF5, Some(0x04)) => frame(record_file_settings(20, 20), [0x91, 0x00]),
+                (0xBB, _) | (0xAF, _) => {
+                    let end = (offset + 59).min(history.len());
+                    let chunk = history[offset..end].to_vec();
+                    offset = end;
+                    let sw = if end < history.len() {
+                        [0x91, 0xAF]
+                    } else {
+                        [0x91, 0x00]
+                    };
+                    frame(chunk, sw)
+                }
+                _ => fake_card(command),
+            }
+        })
+        .unwrap();
+
+        assert!(sent.contains(&Command::read_data(0x01, 0, 40).to_vec()));
+        assert_eq!(card.history.len(), 20);
+    }
+
+    #[test]
+    fn should_not_read_empty_history() {
+        let mut sent = vec![];
+        let card = read_travel_card(&mut |command: &[u8]| {
+            sent.push(command.to_vec());
+            match (command[1], command.get(5)) {
+                (0xF5, Some(0x04)) => frame(record_file_settings(14, 0), [0x91, 0x00]),
+                _ => fake_card(command),
+            }
+        })
+        .unwrap();
+
+        assert!(!sent.iter().any(|command| command[1] == 0xBB));
+        assert!(card.history.is_empty());
+    }
+
+    #[test]
+    fn should_report_missing_application() {
+        let result = read_travel_card(&mut |_: &[u8]| Ok::<_, ()>(vec![0x91, 0xA0]));
+        assert!(matches!(result, Err(ReadError::NotHslCard)));
+    }
+
+    #[test]
+    fn should_reject_short_files() {
+        let result = read_travel_card(&mut |command: &[u8]| match command[1] {
+            0xBD if command[5] == 0x01 => frame(vec![0; 10], [0x91, 0x00]),
+            _ => fake_card(command),
+        });
+        let partial = match result {
+            Err(ReadError::Partial(partial)) => partial,
+            other => panic!("Expected a partial read, got {:?}", other.map(|_| ())),
+        };
+        assert_eq!(partial.failed_files(), vec![CardFile::PeriodPass]);
+        assert!(matches!(
+            partial.failures[0].error,
+            ReadError::FileTooShort {
+                file_id: 0x01,
+                length: 10
+            }
+        ));
+        assert_eq!(partial.read_files().len(), 5);
+        assert_eq!(partial.data(CardFile::StoredValue), Some(&[0; 12][..]));
+        assert!(partial.period_pass().is_none());
+        assert_eq!(partial.stored_value_cents(), Some(0));
+        assert!(partial.application_instance_id().is_some());
+        assert_eq!(partial.history().map(|h| h.len()), Some(5));
+        assert!(partial.e_ticket().is_some());
+    }
+
+    #[test]
+    fn should_reselect_and_retry_after_transport_error() {
+        let mut sent = vec![];
+        let mut failed = false;
+        let card = read_travel_card(&mut |command: &[u8]| {
+            sent.push(command.to_vec());
+            if command[1] == 0xBD && command[5] == 0x02 && !failed {
+                failed = true;
+                return Err(());
+            }
+            fake_card(command)
+        });
+
+        assert!(card.is_ok());
+        // Select, three files, the stored value's settings and failed read, select again,
+        // check the app info, and retry.
+        assert_eq!(sent[8][5], 0x02);
+        assert_eq!(sent[9], SELECT_HSL_COMMAND);
+        assert_eq!(sent[10], READ_APP_INFO_COMMAND);
+        assert_eq!(sent[11], sent[8]);
+    }
+
+    #[test]
+    fn should_keep_files_read_before_giving_up() {
+        let result = read_travel_card_with_policy(
+            &mut |command: &[u8]| match command[1] {
+                0xBB => Err(()),
+                _ => fake_card(command),
+            },
+            RetryPolicy::NONE,
+        );
+        match result {
+            Err(ReadError::Partial(partial)) => {
+                assert_eq!(partial.failed_files(), vec![CardFile::History]);
+                assert_eq!(partial.read_files(), CardFile::ALL[..5].to_vec());
+            }
+            other => panic!("Expected a partial read, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn should_stop_when_card_is_swapped() {
+        let mut failed = false;
+        let result = read_travel_card(&mut |command: &[u8]| {
+            if command[1] == 0xBD && command[5] == 0x02 && !failed {
+                failed = true;
+                return Err(());
+            }
+            if failed && command[1] == 0xBD && command[5] == 0x08 {
+                // Another card's app info.
+                return frame(vec![0xFF; 11], [0x91, 0x00]);
+            }
+            fake_card(command)
+        });
+        assert!(matches!(result, Err(ReadError::CardChanged)));
+
+        let mut failed = false;
+        let result = read_travel_card(&mut |command: &[u8]| {
+            if command[1] == 0xBD && command[5] == 0x02 && !failed {
+                failed = true;
+                return Err(());
+            }
+            if failed && command[1] == 0x5A {
+                return frame(vec![], [0x91, 0xA0]);
+            }
+            fake_card(command)
+        });
+        assert!(matches!(result, Err(ReadError::CardChanged)));
+    }
+
+    #[test]
+    fn should_give_up_when_card_stays_away() {
+        let mut sent = vec![];
+        let result = read_travel_card(&mut |command: &[u8]| {
+            sent.push(command.to_vec());
+            // Select, then the settings and data of two files.
+            if sent.len() > 5 {
+                return Err(());
+            }
+            fake_card(command)
+        });
+        match result {
+            Err(ReadError::Partial(partial)) => {
+                assert_eq!(
+                    partial.read_files(),
+                    vec![CardFile::AppInfo, CardFile::ControlInfo]
+                );
+            }
+            other => panic!("Expected a partial read, got {:?}", other.map(|_| ())),
+        }
+    }
+}
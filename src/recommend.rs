@@ -0,0 +1,336 @@
+//! Comparing what a card's recent trips cost with a period product against value tickets.
+
+use crate::en1545date::helsinki_date;
+use crate::fares::{FareError, FareSchedule};
+use crate::history::TransactionType;
+use crate::models::CustomerProfile;
+use crate::travelcard::TravelCard;
+use chrono::prelude::*;
+use chrono::Duration;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum TicketChoice {
+    PeriodPass,
+    ValueTickets,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TicketRecommendation {
+    /// The first day of the compared window, in Helsinki local time.
+    pub window_start: NaiveDate,
+    /// The last day of the compared window, in Helsinki local time. Inclusive.
+    pub window_end: NaiveDate,
+    /// False if the card's history doesn't reach back to the start of the window,
+    /// so older trips in it may be missing and the value ticket cost is an underestimate.
+    pub history_covers_window: bool,
+    pub trips: u32,
+    /// What the card's most recently loaded period product cost.
+    pub period_price_cents: u32,
+    /// What the trips cost, or would have cost, on value tickets.
+    pub value_ticket_cost_cents: u32,
+    pub period_cost_per_trip_cents: Option<u32>,
+    pub value_ticket_cost_per_trip_cents: Option<u32>,
+    /// What the trips in the window were actually paid with. If any were made on a
+    /// period pass, that's what the user is considered to be using.
+    pub current: TicketChoice,
+    pub cheaper: TicketChoice,
+    /// How much switching from `current` to `cheaper` would have saved. 0 if they're the same.
+    pub savings_cents: u32,
+}
+
+impl TicketRecommendation {
+    pub fn should_switch(&self) -> bool {
+        self.current != self.cheaper
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RecommendationError {
+    /// The card has never had a period product loaded, so there's nothing to compare against.
+    NoPeriodProduct,
+    /// A trip was made on a period pass on this Helsinki local date, but neither period slot
+    /// was valid in a set of zones then, so there's nothing to price the trip in.
+    NoPassZones(NaiveDate),
+    /// No fare table in the schedule applied on this Helsinki local date.
+    NoFareTable(NaiveDate),
+    /// A trip made on a period pass couldn't be priced as a value ticket.
+    Fare(FareError),
+}
+
+impl std::fmt::Display for RecommendationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RecommendationError::NoPeriodProduct => {
+                write!(
+                    f,
+                    "The card has no loaded period product to compare against."
+                )
+            }
+            RecommendationError::NoPassZones(date) => {
+                write!(f, "No zone-based period pass was valid on {}.", date)
+            }
+            RecommendationError::NoFareTable(date) => {
+                write!(f, "No fare table applies on {}.", date)
+            }
+            RecommendationError::Fare(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for RecommendationError {}
+
+impl From<FareError> for RecommendationError {
+    fn from(e: FareError) -> Self {
+        RecommendationError::Fare(e)
+    }
+}
+
+fn per_trip(cost_cents: u32, trips: u32) -> Option<u32> {
+    match trips {
+        0 => None,
+        n => Some(cost_cents / n),
+    }
+}
+
+impl TravelCard {
+    /// Compares the card's most recently loaded period product with value tickets, over
+    /// a window as long as that product, ending on the day of the most recent trip.
+    ///
+    /// Value ticket trips are counted at what was actually charged. Trips made on a period
+    /// pass are priced as single tickets for the given profile, in the zones of the period
+    /// that was valid then, at the fares that applied on the day of the trip.
+    pub fn recommend_ticket(
+        &self,
+        schedule: &FareSchedule,
+        profile: CustomerProfile,
+    ) -> Result<TicketRecommendation, RecommendationError> {
+        let period_days = self.period_pass.loaded_period_length;
+        if period_days == 0 {
+            return Err(RecommendationError::NoPeriodProduct);
+        }
+        let period_price_cents = self.period_pass.loaded_period_price;
+
+        let window_end = self
+            .history
+            .iter()
+            .map(|h| helsinki_date(h.boarding_datetime))
+            .max()
+            .unwrap_or_else(|| helsinki_date(self.period_pass.loaded_period_datetime));
+        let window_start = window_end - Duration::days(i64::from(period_days) - 1);
+        let history_covers_window = self
+            .history
+            .iter()
+            .any(|h| helsinki_date(h.boarding_datetime) < window_start);
+
+        let mut trips = 0;
+        let mut value_ticket_cost_cents = 0;
+        let mut current = TicketChoice::ValueTickets;
+        for entry in self.history.iter().filter(|h| {
+            let date = helsinki_date(h.boarding_datetime);
+            window_start <= date && date <= window_end
+        }) {
+            trips += 1;
+            value_ticket_cost_cents += match entry.transaction_type {
+                TransactionType::ValueTicket => u32::from(entry.ticket_fare_cents),
+                TransactionType::SeasonPass => {
+                    current = TicketChoice::PeriodPass;
+                    let date = helsinki_date(entry.boarding_datetime);
+                    let zones = self
+                        .period_pass
+                        .active_slot_at(entry.boarding_datetime)
+                        .and_then(|slot| slot.validity_area.zones())
+                        .ok_or(RecommendationError::NoPassZones(date))?;
+                    schedule
+                        .table_at(entry.boarding_datetime)
+                        .ok_or(RecommendationError::NoFareTable(date))?
+                        .price(zones, profile, entry.group_size.max(1))?
+                }
+            };
+        }
+
+        let (cheaper, savings_cents) = if value_ticket_cost_cents < period_price_cents {
+            (
+                TicketChoice::ValueTickets,
+                period_price_cents - value_ticket_cost_cents,
+            )
+        } else {
+            (
+                TicketChoice::PeriodPass,
+                value_ticket_cost_cents - period_price_cents,
+            )
+        };
+        let savings_cents = if cheaper == current { 0 } else { savings_cents };
+
+        Ok(TicketRecommendation {
+            window_start,
+            window_end,
+            history_covers_window,
+            trips,
+            period_price_cents,
+            value_ticket_cost_cents,
+            period_cost_per_trip_cents: per_trip(period_price_cents, trips),
+            value_ticket_cost_per_trip_cents: per_trip(value_ticket_cost_cents, trips),
+            current,
+            cheaper,
+            savings_cents,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::fares::{DatedFareTable, FareSchedule, FareTable};
+    use crate::models::CustomerProfile;
+    use crate::recommend::{RecommendationError, TicketChoice};
+    use crate::test_cards::{dump_datetime, en1545_date, trip_dump, CardDump};
+    use chrono::prelude::*;
+
+    const AB: u8 = 0b000_001;
+    const ABC: u8 = 0b000_010;
+
+    fn fare_table(ab: u32, abc: u32) -> FareTable {
+        let mut table = FareTable::new();
+        table.insert("AB".parse().unwrap(), CustomerProfile::Adult, ab);
+        table.insert("ABC".parse().unwrap(), CustomerProfile::Adult, abc);
+        table
+    }
+
+    fn schedule() -> FareSchedule {
+        let mut schedule = FareSchedule::new();
+        schedule.insert(DatedFareTable {
+            effective_from: NaiveDate::from_ymd_opt(2020, 1, 1).unwrap(),
+            effective_to: None,
+            table: fare_table(280, 410),
+        });
+        schedule
+    }
+
+    fn occasional_rider(pass_zones: u8) -> CardDump {
+        let mut dump = CardDump::new();
+        dump.period_pass_1(
+            pass_zones,
+            en1545_date(2020, 2, 1),
+            en1545_date(2020, 3, 31),
+        );
+        dump.period_load(dump_datetime(2020, 2, 1, 10, 0), 30, 5970);
+        dump.push_history(trip_dump(false, dump_datetime(2020, 2, 20, 8, 0), 0, 0));
+        for day in 1..=4 {
+            dump.push_history(trip_dump(
+                false,
+                dump_datetime(2020, 3, day * 7, 8, 0),
+                0,
+                0,
+            ));
+        }
+        dump
+    }
+
+    #[test]
+    fn should_recommend_value_tickets_for_occasional_riders() {
+        let card = occasional_rider(AB).build();
+
+        let recommendation = card
+            .recommend_ticket(&schedule(), CustomerProfile::Adult)
+            .unwrap();
+        assert_eq!(
+            recommendation.window_start,
+            NaiveDate::from_ymd_opt(2020, 2, 28).unwrap()
+        );
+        assert!(recommendation.history_covers_window);
+        assert_eq!(recommendation.trips, 4);
+        assert_eq!(recommendation.value_ticket_cost_cents, 1120);
+        assert_eq!(recommendation.period_cost_per_trip_cents, Some(1492));
+        assert_eq!(recommendation.current, TicketChoice::PeriodPass);
+        assert!(recommendation.should_switch());
+        assert_eq!(recommendation.savings_cents, 4850);
+    }
+
+    #[test]
+    fn should_price_period_trips_in_the_pass_zones() {
+        let card = occasional_rider(ABC).build();
+
+        let recommendation = card
+            .recommend_ticket(&schedule(), CustomerProfile::Adult)
+            .unwrap();
+        assert_eq!(recommendation.value_ticket_cost_cents, 4 * 410);
+    }
+
+    #[test]
+    fn should_price_trips_at_the_fares_of_their_day() {
+        let mut schedule = FareSchedule::new();
+        schedule.insert(DatedFareTable {
+            effective_from: NaiveDate::from_ymd_opt(2020, 1, 1).unwrap(),
+            effective_to: Some(NaiveDate::from_ymd_opt(2020, 3, 15).unwrap()),
+            table: fare_table(280, 410),
+        });
+        schedule.insert(DatedFareTable {
+            effective_from: NaiveDate::from_ymd_opt(2020, 3, 16).unwrap(),
+            effective_to: None,
+            table: fare_table(300, 440),
+        });
+        let card = occasional_rider(AB).build();
+
+        let recommendation = card
+            .recommend_ticket(&schedule, CustomerProfile::Adult)
+            .unwrap();
+        assert_eq!(recommendation.value_ticket_cost_cents, 2 * 280 + 2 * 300);
+    }
+
+    #[test]
+    fn should_require_fares_for_every_trip() {
+        let mut schedule = FareSchedule::new();
+        schedule.insert(DatedFareTable {
+            effective_from: NaiveDate::from_ymd_opt(2020, 3, 10).unwrap(),
+            effective_to: None,
+            table: fare_table(280, 410),
+        });
+        let card = occasional_rider(AB).build();
+
+        assert_eq!(
+            card.recommend_ticket(&schedule, CustomerProfile::Adult)
+                .unwrap_err(),
+            RecommendationError::NoFareTable(NaiveDate::from_ymd_opt(2020, 3, 7).unwrap())
+        );
+    }
+
+    #[test]
+    fn should_require_a_valid_pass_for_period_trips() {
+        let mut dump = occasional_rider(AB);
+        dump.period_pass_1(AB, en1545_date(2020, 2, 1), en1545_date(2020, 3, 10));
+        let card = dump.build();
+
+        assert_eq!(
+            card.recommend_ticket(&schedule(), CustomerProfile::Adult)
+                .unwrap_err(),
+            RecommendationError::NoPassZones(NaiveDate::from_ymd_opt(2020, 3, 14).unwrap())
+        );
+    }
+
+    #[test]
+    fn should_not_recommend_switching_to_the_current_choice() {
+        let mut dump = CardDump::new();
+        dump.period_load(dump_datetime(2020, 2, 1, 10, 0), 14, 1000);
+        for day in 1..=5 {
+            dump.push_history(trip_dump(true, dump_datetime(2020, 3, day, 8, 0), 280, 0));
+        }
+        let card = dump.build();
+
+        let recommendation = card
+            .recommend_ticket(&schedule(), CustomerProfile::Adult)
+            .unwrap();
+        assert_eq!(recommendation.current, TicketChoice::ValueTickets);
+        assert_eq!(recommendation.cheaper, TicketChoice::PeriodPass);
+        assert_eq!(recommendation.savings_cents, 400);
+        assert!(!recommendation.history_covers_window);
+    }
+
+    #[test]
+    fn should_require_a_period_product() {
+        let card = CardDump::new().build();
+        assert_eq!(
+            card.recommend_ticket(&schedule(), CustomerProfile::Adult)
+                .unwrap_err(),
+            RecommendationError::NoPeriodProduct
+        );
+    }
+}
@@ -0,0 +1,216 @@
+//! Resolves the organization IDs and device numbers stored on the card to human-readable names.
+//!
+//! None of this data is on the card itself, so it's loaded from a JSON file that can be updated
+//! without recompiling. The file looks like this:
+//!
+//! ```json
+//! {
+//!     "organizations": [
+//!         {
+//!             "id": 1,
+//!             "name": "HSL",
+//!             "devices": [{ "number": 2, "name": "Kamppi service point" }]
+//!         }
+//!     ],
+//!     "sale_devices": [{ "device_type": 4, "number": 3, "name": "HSL app" }]
+//! }
+//! ```
+//!
+//! `device_type` is the raw `SaleDevice` type, i.e. 0 for `ServicePointSalesDevice`, 4 for `Server`, etc.
+//! Both lists, and each organization's `devices`, may be omitted.
+
+use crate::models::SaleDevice;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// A lookup table of organizations, and the devices belonging to them.
+#[derive(Debug, Default)]
+pub struct Registry {
+    organizations: HashMap<u16, Organization>,
+    sale_devices: HashMap<(u8, u16), String>,
+}
+
+#[derive(Debug)]
+struct Organization {
+    name: String,
+    devices: HashMap<u16, String>,
+}
+
+#[derive(Debug)]
+pub enum RegistryError {
+    Io(std::io::Error),
+    Parse(serde_json::Error),
+}
+
+impl std::fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RegistryError::Io(e) => write!(f, "Failed to read registry file: {}", e),
+            RegistryError::Parse(e) => write!(f, "Failed to parse registry data: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for RegistryError {}
+
+impl From<std::io::Error> for RegistryError {
+    fn from(e: std::io::Error) -> Self {
+        RegistryError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for RegistryError {
+    fn from(e: serde_json::Error) -> Self {
+        RegistryError::Parse(e)
+    }
+}
+
+// --- On-disk format ---
+#[derive(Deserialize)]
+struct RegistryFile {
+    #[serde(default)]
+    organizations: Vec<OrganizationEntry>,
+    #[serde(default)]
+    sale_devices: Vec<SaleDeviceEntry>,
+}
+
+#[derive(Deserialize)]
+struct OrganizationEntry {
+    id: u16,
+    name: String,
+    #[serde(default)]
+    devices: Vec<DeviceEntry>,
+}
+
+#[derive(Deserialize)]
+struct DeviceEntry {
+    number: u16,
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct SaleDeviceEntry {
+    device_type: u8,
+    number: u16,
+    name: String,
+}
+
+impl Registry {
+    /// Loads a registry from the JSON file at the given path.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Registry, RegistryError> {
+        let json = std::fs::read_to_string(path)?;
+        Registry::from_json(&json)
+    }
+
+    /// Parses a registry from a JSON string.
+    pub fn from_json(json: &str) -> Result<Registry, RegistryError> {
+        let file: RegistryFile = serde_json::from_str(json)?;
+        let organizations = file
+            .organizations
+            .into_iter()
+            .map(|org| {
+                let devices = org
+                    .devices
+                    .into_iter()
+                    .map(|d| (d.number, d.name))
+                    .collect();
+                (
+                    org.id,
+                    Organization {
+                        name: org.name,
+                        devices,
+                    },
+                )
+            })
+            .collect();
+        let sale_devices = file
+            .sale_devices
+            .into_iter()
+            .map(|d| ((d.device_type, d.number), d.name))
+            .collect();
+        Ok(Registry {
+            organizations,
+            sale_devices,
+        })
+    }
+
+    /// The name of the organization with the given ID, if known.
+    pub fn organization_name(&self, organization_id: u16) -> Option<&str> {
+        self.organizations
+            .get(&organization_id)
+            .map(|org| org.name.as_str())
+    }
+
+    /// The name of the given organization's device, if known.
+    pub fn device_name(&self, organization_id: u16, device_number: u16) -> Option<&str> {
+        self.organizations
+            .get(&organization_id)
+            .and_then(|org| org.devices.get(&device_number))
+            .map(|name| name.as_str())
+    }
+
+    /// The name of the given sale device, if known.
+    pub fn sale_device_name(&self, sale_device: &SaleDevice) -> Option<&str> {
+        self.sale_devices
+            .get(&(sale_device.device_type(), u16::from(sale_device)))
+            .map(|name| name.as_str())
+    }
+
+    /// Describes where a load happened, e.g. "HSL, Kamppi service point".
+    /// Anything that can't be resolved falls back to its raw number.
+    pub fn describe_load(&self, organization_id: u16, device_number: u16) -> String {
+        let organization = match self.organization_name(organization_id) {
+            Some(name) => name.to_string(),
+            None => format!("Organization {}", organization_id),
+        };
+        match self.device_name(organization_id, device_number) {
+            Some(device) => format!("{}, {}", organization, device),
+            None => format!("{}, device {}", organization, device_number),
+        }
+    }
+
+    /// Describes a sale device, e.g. "HSL app".
+    /// Falls back to "Unknown device (N)", N being the device's number, if it can't be resolved.
+    pub fn describe_sale_device(&self, sale_device: &SaleDevice) -> String {
+        match self.sale_device_name(sale_device) {
+            Some(name) => name.to_string(),
+            None => format!("Unknown device ({})", u16::from(sale_device)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::models::SaleDevice;
+    use crate::registry::Registry;
+
+    const REGISTRY_JSON: &str = r#"{
+        "organizations": [
+            { "id": 1, "name": "HSL", "devices": [{ "number": 2, "name": "Kamppi service point" }] },
+            { "id": 5, "name": "Some operator" }
+        ],
+        "sale_devices": [{ "device_type": 4, "number": 3, "name": "HSL app" }]
+    }"#;
+
+    #[test]
+    fn should_resolve_known_organizations_and_devices() {
+        let registry = Registry::from_json(REGISTRY_JSON).unwrap();
+        assert_eq!(registry.describe_load(1, 2), "HSL, Kamppi service point");
+        assert_eq!(registry.describe_load(5, 2), "Some operator, device 2");
+        assert_eq!(
+            registry.describe_sale_device(&SaleDevice::Server(3)),
+            "HSL app"
+        );
+    }
+
+    #[test]
+    fn should_fall_back_to_raw_numbers() {
+        let registry = Registry::from_json("{}").unwrap();
+        assert_eq!(registry.describe_load(7, 8), "Organization 7, device 8");
+        assert_eq!(
+            registry.describe_sale_device(&SaleDevice::CardReader(9)),
+            "Unknown device (9)"
+        );
+    }
+}
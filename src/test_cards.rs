@@ -0,0 +1,220 @@
+// Builders for synthetic card dumps, shared by the tests in this crate.
+// Bit offsets mirror the ones used by the parsers in travelcard.rs, eticket.rs and history.rs.
+
+use crate::conversion::set_bits;
+use crate::history::{History, TransactionType};
+use crate::models::ValidityArea;
+use crate::travelcard::{create_travel_card, TravelCard};
+use chrono::prelude::*;
+
+/// The En1545 day number (days since 1997-01-01) of the given date.
+pub(crate) fn en1545_date(year: i32, month: u32, day: u32) -> u64 {
+    let zero = NaiveDate::from_ymd_opt(1997, 1, 1).unwrap();
+    let date = NaiveDate::from_ymd_opt(year, month, day).unwrap();
+    (date - zero).num_days() as u64
+}
+
+/// The En1545 time (minutes since midnight) of the given time.
+pub(crate) fn en1545_time(hour: u64, minute: u64) -> u64 {
+    hour * 60 + minute
+}
+
+/// A UTC instant, given as Helsinki local time.
+pub(crate) fn helsinki(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+    chrono_tz::Europe::Helsinki
+        .with_ymd_and_hms(year, month, day, hour, minute, 0)
+        .unwrap()
+        .with_timezone(&Utc)
+}
+
+/// An (En1545 date, En1545 time) pair for the given Helsinki local time.
+pub(crate) type DumpDateTime = (u64, u64);
+
+pub(crate) fn dump_datetime(
+    year: i32,
+    month: u32,
+    day: u32,
+    hour: u64,
+    minute: u64,
+) -> DumpDateTime {
+    (en1545_date(year, month, day), en1545_time(hour, minute))
+}
+
+/// A single history entry, as stored on the card.
+pub(crate) struct HistoryDump {
+    pub value_ticket: bool,
+    pub boarding: DumpDateTime,
+    pub transfer_end: DumpDateTime,
+    pub fare_cents: u64,
+    pub group_size: u64,
+    pub remaining_value: u64,
+}
+
+/// A single-person trip boarded at the given Helsinki local time, with an 80 minute
+/// transfer window that must end on the same day.
+pub(crate) fn trip_dump(
+    value_ticket: bool,
+    boarding: DumpDateTime,
+    fare_cents: u64,
+    remaining_value: u64,
+) -> HistoryDump {
+    HistoryDump {
+        value_ticket,
+        boarding,
+        transfer_end: (boarding.0, boarding.1 + 80),
+        fare_cents,
+        group_size: 1,
+        remaining_value,
+    }
+}
+
+/// The same trip as `trip_dump`, as decoded from a card.
+pub(crate) fn trip(
+    transaction_type: TransactionType,
+    boarding: DateTime<Utc>,
+    fare_cents: u16,
+    remaining_value: u32,
+) -> History {
+    History {
+        transaction_type,
+        boarding_datetime: boarding,
+        transfer_end_datetime: boarding + chrono::Duration::minutes(80),
+        ticket_fare_cents: fare_cents,
+        group_size: 1,
+        remaining_value,
+    }
+}
+
+pub(crate) struct CardDump {
+    pub app_info: [u8; 11],
+    pub control_info: [u8; 10],
+    pub period_pass: [u8; 35],
+    pub stored_value: [u8; 12],
+    pub e_ticket: [u8; 45],
+    pub history: Vec<u8>,
+}
+
+impl CardDump {
+    /// An all-zero card, i.e. one without any products, value or history.
+    pub fn new() -> CardDump {
+        CardDump {
+            app_info: [0; 11],
+            control_info: [0; 10],
+            period_pass: [0; 35],
+            stored_value: [0; 12],
+            e_ticket: [0; 45],
+            history: vec![],
+        }
+    }
+
+    /// Sets the card's instance ID. Only the first byte is set, which is enough to tell cards apart.
+    pub fn instance_id(&mut self, id: u8) {
+        self.app_info[1] = id;
+    }
+
+    pub fn issuing_date(&mut self, date: u64) {
+        set_bits(&mut self.control_info, 0, 14, date);
+    }
+
+    pub fn counters(&mut self, transaction_counter: u64, action_list_counter: u64) {
+        set_bits(&mut self.control_info, 24, 24, transaction_counter);
+        set_bits(&mut self.control_info, 48, 32, action_list_counter);
+    }
+
+    /// Sets period pass slot 1 to a new-style zone product, using the on-card from/to zone encoding.
+    pub fn period_pass_1(&mut self, zones: u8, start_date: u64, end_date: u64) {
+        set_bits(
+            &mut self.period_pass,
+            15,
+            2,
+            u64::from(ValidityArea::NEW_ZONE_TYPE),
+        );
+        set_bits(&mut self.period_pass, 17, 6, u64::from(zones));
+        set_bits(&mut self.period_pass, 23, 14, start_date);
+        set_bits(&mut self.period_pass, 37, 14, end_date);
+    }
+
+    /// Sets period pass slot 2 to a new-style zone product, using the on-card from/to zone encoding.
+    pub fn period_pass_2(&mut self, zones: u8, start_date: u64, end_date: u64) {
+        set_bits(
+            &mut self.period_pass,
+            71,
+            2,
+            u64::from(ValidityArea::NEW_ZONE_TYPE),
+        );
+        set_bits(&mut self.period_pass, 73, 6, u64::from(zones));
+        set_bits(&mut self.period_pass, 79, 14, start_date);
+        set_bits(&mut self.period_pass, 93, 14, end_date);
+    }
+
+    pub fn period_load(&mut self, loaded: DumpDateTime, length_days: u64, price_cents: u64) {
+        set_bits(&mut self.period_pass, 127, 14, loaded.0);
+        set_bits(&mut self.period_pass, 141, 11, loaded.1);
+        set_bits(&mut self.period_pass, 152, 9, length_days);
+        set_bits(&mut self.period_pass, 161, 20, price_cents);
+    }
+
+    pub fn stored_value(&mut self, cents: u64) {
+        set_bits(&mut self.stored_value, 0, 20, cents);
+    }
+
+    pub fn last_load(&mut self, loaded: DumpDateTime, value_cents: u64) {
+        set_bits(&mut self.stored_value, 20, 14, loaded.0);
+        set_bits(&mut self.stored_value, 34, 11, loaded.1);
+        set_bits(&mut self.stored_value, 45, 20, value_cents);
+    }
+
+    /// Sets the eTicket to a new-style zone ticket, using the on-card from/to zone encoding.
+    pub fn e_ticket(&mut self, zones: u8, start: DumpDateTime, end: DumpDateTime) {
+        set_bits(
+            &mut self.e_ticket,
+            61,
+            2,
+            u64::from(ValidityArea::NEW_ZONE_TYPE),
+        );
+        set_bits(&mut self.e_ticket, 63, 6, u64::from(zones));
+        set_bits(&mut self.e_ticket, 205, 14, start.0);
+        set_bits(&mut self.e_ticket, 219, 11, start.1);
+        set_bits(&mut self.e_ticket, 230, 14, end.0);
+        set_bits(&mut self.e_ticket, 244, 11, end.1);
+        self.e_ticket_validity_status(true);
+    }
+
+    pub fn e_ticket_validity_status(&mut self, valid: bool) {
+        set_bits(&mut self.e_ticket, 285, 1, valid as u64);
+    }
+
+    pub fn e_ticket_validity_length(&mut self, length_type: u64, length: u64) {
+        set_bits(&mut self.e_ticket, 41, 2, length_type);
+        set_bits(&mut self.e_ticket, 43, 8, length);
+    }
+
+    pub fn e_ticket_boarding(&mut self, boarded: DumpDateTime) {
+        set_bits(&mut self.e_ticket, 286, 14, boarded.0);
+        set_bits(&mut self.e_ticket, 300, 11, boarded.1);
+    }
+
+    pub fn push_history(&mut self, entry: HistoryDump) {
+        let mut bytes = [0u8; 12];
+        set_bits(&mut bytes, 0, 1, entry.value_ticket as u64);
+        set_bits(&mut bytes, 1, 14, entry.boarding.0);
+        set_bits(&mut bytes, 15, 11, entry.boarding.1);
+        set_bits(&mut bytes, 26, 14, entry.transfer_end.0);
+        set_bits(&mut bytes, 40, 11, entry.transfer_end.1);
+        set_bits(&mut bytes, 51, 14, entry.fare_cents);
+        set_bits(&mut bytes, 65, 6, entry.group_size);
+        set_bits(&mut bytes, 71, 20, entry.remaining_value);
+        self.history.extend_from_slice(&bytes);
+    }
+
+    pub fn build(&self) -> TravelCard {
+        create_travel_card(
+            &self.app_info,
+            &self.control_info,
+            &self.period_pass,
+            &self.stored_value,
+            &self.e_ticket,
+            &self.history,
+        )
+    }
+}
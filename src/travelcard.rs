@@ -1,9 +1,15 @@
+use crate::clock::Clock;
 use crate::conversion::*;
-use crate::en1545date::{from_en1545_date, from_en1545_date_and_time};
+use crate::en1545date::{
+    from_en1545_date, from_en1545_date_and_time, from_en1545_date_local, helsinki_date,
+    helsinki_midnight,
+};
 use crate::eticket::*;
 use crate::history::*;
 use crate::models::*;
+use crate::registry::Registry;
 use chrono::prelude::*;
+use chrono::Duration;
 
 #[derive(Debug)]
 pub struct TravelCard {
@@ -40,17 +46,18 @@ pub struct TravelCard {
 
 #[derive(Debug)]
 pub struct PeriodPass {
+    // Period dates are calendar dates in Helsinki local time.
     pub product_code_1: ProductCode,
     pub validity_area_1: ValidityArea,
-    pub period_start_date_1: Date<Utc>,
-    pub period_end_date_1: Date<Utc>,
+    pub period_start_date_1: NaiveDate,
+    pub period_end_date_1: NaiveDate,
 
     // This _seems_ to be the last-known season pass before the switchover to the new card format.
     // Probably part of the migration path when they were doing the changeover.
     pub product_code_2: ProductCode,
     pub validity_area_2: ValidityArea,
-    pub period_start_date_2: Date<Utc>,
-    pub period_end_date_2: Date<Utc>,
+    pub period_start_date_2: NaiveDate,
+    pub period_end_date_2: NaiveDate,
 
     // Most recent card load:
     pub loaded_period_product: ProductCode,
@@ -68,6 +75,133 @@ pub struct PeriodPass {
     pub last_board_area: BoardingArea,
 }
 
+impl TravelCard {
+    /// A human-readable description of where the stored value was last loaded,
+    /// resolved through the given registry.
+    pub fn last_load_description(&self, registry: &Registry) -> String {
+        registry.describe_load(self.last_load_organization_id, self.last_load_device_num)
+    }
+}
+
+/// One of the two period products stored in a `PeriodPass`.
+#[derive(Debug, Copy, Clone)]
+pub struct PeriodSlot<'a> {
+    pub product_code: &'a ProductCode,
+    pub validity_area: &'a ValidityArea,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+}
+
+impl PeriodSlot<'_> {
+    /// The instant this period begins: Helsinki midnight at the start of `start_date`.
+    pub fn valid_from(&self) -> DateTime<Utc> {
+        helsinki_midnight(self.start_date)
+    }
+
+    /// The instant this period ends: Helsinki midnight at the end of `end_date`.
+    pub fn valid_until(&self) -> DateTime<Utc> {
+        helsinki_midnight(self.end_date + Duration::days(1))
+    }
+
+    pub fn is_active_at(&self, now: DateTime<Utc>) -> bool {
+        self.valid_from() <= now && now < self.valid_until()
+    }
+
+    /// True if this period starts after the given time.
+    pub fn is_queued_at(&self, now: DateTime<Utc>) -> bool {
+        now < self.valid_from()
+    }
+
+    /// The number of Helsinki local days left in this period at the given time, counting both
+    /// today and the last day. A period that hasn't started yet counts all of its days,
+    /// and an expired one has 0.
+    pub fn remaining_days_at(&self, now: DateTime<Utc>) -> i64 {
+        let first_day = std::cmp::max(helsinki_date(now), self.start_date);
+        std::cmp::max((self.end_date - first_day).num_days() + 1, 0)
+    }
+}
+
+impl PeriodPass {
+    /// Both period product slots, in card order.
+    pub fn slots(&self) -> [PeriodSlot<'_>; 2] {
+        [
+            PeriodSlot {
+                product_code: &self.product_code_1,
+                validity_area: &self.validity_area_1,
+                start_date: self.period_start_date_1,
+                end_date: self.period_end_date_1,
+            },
+            PeriodSlot {
+                product_code: &self.product_code_2,
+                validity_area: &self.validity_area_2,
+                start_date: self.period_start_date_2,
+                end_date: self.period_end_date_2,
+            },
+        ]
+    }
+
+    /// The slot that is valid at the given time, if any. If both are, the one that ends last.
+    pub fn active_slot_at(&self, now: DateTime<Utc>) -> Option<PeriodSlot<'_>> {
+        self.slots()
+            .iter()
+            .filter(|slot| slot.is_active_at(now))
+            .max_by_key(|slot| slot.end_date)
+            .copied()
+    }
+
+    /// The number of Helsinki local days left on the active period at the given time,
+    /// counting both today and the last day. None if no period is active.
+    pub fn remaining_days_at(&self, now: DateTime<Utc>) -> Option<i64> {
+        self.active_slot_at(now)
+            .map(|slot| slot.remaining_days_at(now))
+    }
+
+    /// The slot holding a period that starts after the given time, if any.
+    pub fn queued_slot_at(&self, now: DateTime<Utc>) -> Option<PeriodSlot<'_>> {
+        self.slots()
+            .iter()
+            .filter(|slot| slot.is_queued_at(now))
+            .min_by_key(|slot| slot.start_date)
+            .copied()
+    }
+
+    /// The next time any period, active or queued, runs out after the given time.
+    pub fn next_expiry_at(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.slots()
+            .iter()
+            .map(|slot| slot.valid_until())
+            .filter(|valid_until| *valid_until > now)
+            .min()
+    }
+
+    /// The slot that is valid now, if any. If both are, the one that ends last.
+    pub fn active_slot(&self, clock: &dyn Clock) -> Option<PeriodSlot<'_>> {
+        self.active_slot_at(clock.now())
+    }
+
+    /// The number of Helsinki local days left on the active period,
+    /// counting both today and the last day. None if no period is active.
+    pub fn remaining_days(&self, clock: &dyn Clock) -> Option<i64> {
+        self.remaining_days_at(clock.now())
+    }
+
+    /// The slot holding a period that hasn't started yet, if any.
+    pub fn queued_slot(&self, clock: &dyn Clock) -> Option<PeriodSlot<'_>> {
+        self.queued_slot_at(clock.now())
+    }
+
+    /// The next time any period, active or queued, runs out.
+    pub fn next_expiry(&self, clock: &dyn Clock) -> Option<DateTime<Utc>> {
+        self.next_expiry_at(clock.now())
+    }
+
+    /// A human-readable description of where the period product was last loaded,
+    /// resolved through the given registry.
+    pub fn loading_description(&self, registry: &Registry) -> String {
+        registry.describe_load(self.loading_organization, self.loading_device_number)
+    }
+}
+
 pub fn create_travel_card(
     app_info: &[u8],
     control_info: &[u8],
@@ -121,7 +255,7 @@ pub fn create_travel_card(
 // Notes about travel card data: All data is presented as a pile of bytes,
 // and all bytes are expressed in Big Endian format.
 
-fn read_application_info(app_info: &[u8]) -> (u8, u8, String, u8, bool) {
+pub(crate) fn read_application_info(app_info: &[u8]) -> (u8, u8, String, u8, bool) {
     (
         get_bits_as_u8(app_info, 0, 4),       // Application Version
         get_bits_as_u8(app_info, 4, 4), // Application Key Version (though the spec sheet marks it as "reserved")
@@ -143,7 +277,7 @@ fn read_control_info(control_info: &[u8]) -> (DateTime<Utc>, bool, u8, u32, u32)
     )
 }
 
-fn read_period_pass(period_pass: &[u8]) -> PeriodPass {
+pub(crate) fn read_period_pass(period_pass: &[u8]) -> PeriodPass {
     let product_code_type_1 = get_bits_as_u8(period_pass, 0, 1);
     let product_code_1 = get_bits_as_u16(period_pass, 1, 14);
     let validity_area_type_1 = get_bits_as_u8(period_pass, 15, 2);
@@ -177,13 +311,13 @@ fn read_period_pass(period_pass: &[u8]) -> PeriodPass {
     PeriodPass {
         product_code_1: ProductCode::new(product_code_type_1, product_code_1),
         validity_area_1: ValidityArea::new(validity_area_type_1, validity_area_1),
-        period_start_date_1: from_en1545_date(start_date_1).date(),
-        period_end_date_1: from_en1545_date(end_date_1).date(),
+        period_start_date_1: from_en1545_date_local(start_date_1),
+        period_end_date_1: from_en1545_date_local(end_date_1),
 
         product_code_2: ProductCode::new(product_code_type_2, product_code_2),
         validity_area_2: ValidityArea::new(validity_area_type_2, validity_area_2),
-        period_start_date_2: from_en1545_date(start_date_2).date(),
-        period_end_date_2: from_en1545_date(end_date_2).date(),
+        period_start_date_2: from_en1545_date_local(start_date_2),
+        period_end_date_2: from_en1545_date_local(end_date_2),
 
         loaded_period_product: ProductCode::new(loaded_period_product_type, loaded_period_product),
         loaded_period_datetime: from_en1545_date_and_time(loaded_period_date, loaded_period_time),
@@ -203,7 +337,7 @@ fn read_period_pass(period_pass: &[u8]) -> PeriodPass {
     }
 }
 
-fn read_stored_value(stored_value: &[u8]) -> StoredValue {
+pub(crate) fn read_stored_value(stored_value: &[u8]) -> StoredValue {
     let last_load_date = get_bits_as_u16(stored_value, 20, 14);
     let last_load_time = get_bits_as_u16(stored_value, 34, 11);
 
@@ -216,10 +350,84 @@ fn read_stored_value(stored_value: &[u8]) -> StoredValue {
     }
 }
 
-struct StoredValue {
-    cents: u32,
+pub(crate) struct StoredValue {
+    pub(crate) cents: u32,
     last_load_datetime: DateTime<Utc>,
     last_load_value: u32,
     last_load_organization_id: u16,
     last_load_device_num: u16,
 }
+
+#[cfg(test)]
+mod test {
+    use crate::clock::FixedClock;
+    use crate::test_cards::{en1545_date, helsinki, CardDump};
+    use chrono::prelude::*;
+    use chrono::Duration;
+
+    /// A card with an AB period pass for June 2020, and a queued ABC period pass for July 2020.
+    fn card_dump() -> CardDump {
+        let mut dump = CardDump::new();
+        dump.period_pass_1(0b000_001, en1545_date(2020, 6, 1), en1545_date(2020, 6, 30));
+        dump.period_pass_2(0b000_010, en1545_date(2020, 7, 1), en1545_date(2020, 7, 31));
+        dump
+    }
+
+    #[test]
+    fn remaining_days_should_count_local_days_including_the_last() {
+        let period_pass = card_dump().build().period_pass;
+        // 00:30 in Helsinki is still the previous day in UTC.
+        let now = helsinki(2020, 6, 30, 0, 30);
+        let active = period_pass.active_slot_at(now).unwrap();
+        assert_eq!(
+            active.start_date,
+            NaiveDate::from_ymd_opt(2020, 6, 1).unwrap()
+        );
+        assert_eq!(period_pass.remaining_days_at(now), Some(1));
+        assert_eq!(
+            period_pass.remaining_days_at(helsinki(2020, 6, 1, 8, 0)),
+            Some(30)
+        );
+    }
+
+    #[test]
+    fn should_find_queued_pass_and_next_expiry() {
+        let period_pass = card_dump().build().period_pass;
+        let now = helsinki(2020, 6, 15, 12, 0);
+        let queued = period_pass.queued_slot_at(now).unwrap();
+        assert_eq!(
+            queued.end_date,
+            NaiveDate::from_ymd_opt(2020, 7, 31).unwrap()
+        );
+        assert_eq!(queued.remaining_days_at(now), 31);
+        assert_eq!(
+            period_pass.next_expiry_at(now),
+            Some(helsinki(2020, 7, 1, 0, 0))
+        );
+
+        let later = helsinki(2020, 7, 2, 12, 0);
+        assert!(period_pass.queued_slot_at(later).is_none());
+        assert_eq!(period_pass.remaining_days_at(later), Some(30));
+        assert_eq!(
+            period_pass.next_expiry_at(later),
+            Some(helsinki(2020, 8, 1, 0, 0))
+        );
+        assert_eq!(period_pass.next_expiry_at(helsinki(2020, 8, 1, 0, 0)), None);
+    }
+
+    #[test]
+    fn should_follow_the_given_clock() {
+        let period_pass = card_dump().build().period_pass;
+        let clock = FixedClock::new(helsinki(2020, 6, 29, 12, 0));
+        assert_eq!(period_pass.remaining_days(&clock), Some(2));
+        assert!(period_pass.queued_slot(&clock).is_some());
+
+        clock.advance(Duration::days(2));
+        assert_eq!(period_pass.remaining_days(&clock), Some(31));
+        assert!(period_pass.queued_slot(&clock).is_none());
+        assert_eq!(
+            period_pass.next_expiry(&clock),
+            Some(helsinki(2020, 8, 1, 0, 0))
+        );
+    }
+}
@@ -0,0 +1,271 @@
+use crate::clock::Clock;
+use crate::eticket::ETicketState;
+use crate::models::{ValidityArea, ValidityZone, VehicleType};
+use crate::travelcard::TravelCard;
+use chrono::prelude::*;
+
+/// A product on the card that can make it valid for travel.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum CardProduct {
+    /// The first period pass slot.
+    PeriodPass1,
+    /// The second period pass slot.
+    PeriodPass2,
+    ETicket,
+}
+
+/// Why a product doesn't make the card valid.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum InvalidReason {
+    /// The product's validity period hasn't started yet.
+    NotYetValid,
+    /// The product's validity period has ended.
+    Expired,
+    /// The product isn't valid in the requested zone.
+    WrongZone,
+    /// The product is only valid on a different vehicle type.
+    WrongVehicle,
+    /// The product is only valid on a specific vehicle type, but no vehicle type was given.
+    VehicleNotSpecified,
+    /// The product's validity area can't be interpreted, e.g. an unknown old-style region.
+    UnknownArea,
+    /// The card marks the eTicket as not valid, even though its validity period hasn't ended.
+    Invalidated,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ValidProduct {
+    pub product: CardProduct,
+    pub valid_until: DateTime<Utc>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct InvalidProduct {
+    pub product: CardProduct,
+    pub reason: InvalidReason,
+}
+
+/// The result of asking whether a card can be used for a given trip.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidityReport {
+    /// The product that makes the card valid. If several do, the one that stays valid longest.
+    pub valid_product: Option<ValidProduct>,
+    /// Every product that doesn't make the card valid, and why.
+    pub invalid_products: Vec<InvalidProduct>,
+}
+
+impl ValidityReport {
+    pub fn is_valid(&self) -> bool {
+        self.valid_product.is_some()
+    }
+}
+
+impl TravelCard {
+    /// Checks whether any product on the card is valid for a trip right now, in the
+    /// given zone and, optionally, on the given vehicle type.
+    pub fn is_valid_now(
+        &self,
+        clock: &dyn Clock,
+        zone: ValidityZone,
+        vehicle: Option<VehicleType>,
+    ) -> ValidityReport {
+        self.is_valid_at(clock.now(), zone, vehicle)
+    }
+
+    /// Checks whether any product on the card is valid for a trip at the given time, in the
+    /// given zone and, optionally, on the given vehicle type.
+    pub fn is_valid_at(
+        &self,
+        time: DateTime<Utc>,
+        zone: ValidityZone,
+        vehicle: Option<VehicleType>,
+    ) -> ValidityReport {
+        let [slot_1, slot_2] = self.period_pass.slots();
+        let e_ticket_rejection = match self.e_ticket.state_at(time) {
+            ETicketState::NotYetValid { .. } => Some(InvalidReason::NotYetValid),
+            ETicketState::Expired { .. } => Some(InvalidReason::Expired),
+            ETicketState::Invalidated => Some(InvalidReason::Invalidated),
+            ETicketState::Active { .. } | ETicketState::InTransferWindow { .. } => None,
+        };
+        let candidates = [
+            (
+                CardProduct::PeriodPass1,
+                slot_1.validity_area,
+                slot_1.valid_until(),
+                check_period(time, slot_1.valid_from(), slot_1.valid_until()),
+            ),
+            (
+                CardProduct::PeriodPass2,
+                slot_2.validity_area,
+                slot_2.valid_until(),
+                check_period(time, slot_2.valid_from(), slot_2.valid_until()),
+            ),
+            (
+                CardProduct::ETicket,
+                &self.e_ticket.validity_area,
+                self.e_ticket.effective_validity_end(),
+                e_ticket_rejection,
+            ),
+        ];
+
+        let mut valid_product: Option<ValidProduct> = None;
+        let mut invalid_products = vec![];
+        for (product, area, valid_until, time_rejection) in candidates.iter() {
+            let rejection = time_rejection.or_else(|| check_area(area, zone, vehicle));
+
+            match rejection {
+                Some(reason) => invalid_products.push(InvalidProduct {
+                    product: *product,
+                    reason,
+                }),
+                None => {
+                    let lasts_longer = valid_product
+                        .map(|v| *valid_until > v.valid_until)
+                        .unwrap_or(true);
+                    if lasts_longer {
+                        valid_product = Some(ValidProduct {
+                            product: *product,
+                            valid_until: *valid_until,
+                        });
+                    }
+                }
+            }
+        }
+
+        ValidityReport {
+            valid_product,
+            invalid_products,
+        }
+    }
+}
+
+/// Returns the reason the time is outside the period, or None if it's inside it.
+fn check_period(
+    time: DateTime<Utc>,
+    valid_from: DateTime<Utc>,
+    valid_until: DateTime<Utc>,
+) -> Option<InvalidReason> {
+    if time < valid_from {
+        Some(InvalidReason::NotYetValid)
+    } else if time >= valid_until {
+        Some(InvalidReason::Expired)
+    } else {
+        None
+    }
+}
+
+/// Returns the reason the area rules out the trip, or None if the area covers it.
+fn check_area(
+    area: &ValidityArea,
+    zone: ValidityZone,
+    vehicle: Option<VehicleType>,
+) -> Option<InvalidReason> {
+    let zones = match area {
+        ValidityArea::Zone(zones) => *zones,
+        ValidityArea::OldZone(old_zone) => match old_zone.to_new_zones() {
+            Some(zones) => zones,
+            None => return Some(InvalidReason::UnknownArea),
+        },
+        ValidityArea::Vehicle(valid_vehicle) => {
+            return match vehicle {
+                Some(v) if v == *valid_vehicle => None,
+                Some(_) => Some(InvalidReason::WrongVehicle),
+                None => Some(InvalidReason::VehicleNotSpecified),
+            };
+        }
+    };
+    if zones.contains(zone) {
+        None
+    } else {
+        Some(InvalidReason::WrongZone)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::models::{ValidityZone, VehicleType};
+    use crate::test_cards::{dump_datetime, en1545_date, helsinki, CardDump};
+    use crate::validity::{CardProduct, InvalidProduct, InvalidReason};
+
+    /// A card with an AB period pass for June 2020, and an expired ABC single ticket.
+    fn card_dump() -> CardDump {
+        let mut dump = CardDump::new();
+        dump.period_pass_1(0b000_001, en1545_date(2020, 6, 1), en1545_date(2020, 6, 30));
+        dump.e_ticket(
+            0b000_010,
+            dump_datetime(2020, 5, 1, 12, 0),
+            dump_datetime(2020, 5, 1, 13, 20),
+        );
+        dump
+    }
+
+    #[test]
+    fn period_pass_should_be_valid_through_its_last_local_day() {
+        let card = card_dump().build();
+        let report = card.is_valid_at(helsinki(2020, 6, 30, 23, 59), ValidityZone::ZoneB, None);
+        let valid = report.valid_product.unwrap();
+        assert_eq!(valid.product, CardProduct::PeriodPass1);
+        assert_eq!(valid.valid_until, helsinki(2020, 7, 1, 0, 0));
+        assert!(report.invalid_products.contains(&InvalidProduct {
+            product: CardProduct::ETicket,
+            reason: InvalidReason::Expired,
+        }));
+
+        let report = card.is_valid_at(helsinki(2020, 7, 1, 0, 0), ValidityZone::ZoneB, None);
+        assert!(!report.is_valid());
+    }
+
+    #[test]
+    fn should_report_wrong_zone() {
+        let card = card_dump().build();
+        let report = card.is_valid_at(
+            helsinki(2020, 6, 15, 8, 0),
+            ValidityZone::ZoneC,
+            Some(VehicleType::Bus),
+        );
+        assert!(!report.is_valid());
+        assert!(report.invalid_products.contains(&InvalidProduct {
+            product: CardProduct::PeriodPass1,
+            reason: InvalidReason::WrongZone,
+        }));
+    }
+
+    #[test]
+    fn should_prefer_the_product_valid_longest() {
+        let mut dump = card_dump();
+        dump.e_ticket(
+            0b000_010,
+            dump_datetime(2020, 6, 15, 7, 30),
+            dump_datetime(2020, 6, 15, 8, 50),
+        );
+        let card = dump.build();
+
+        let report = card.is_valid_at(helsinki(2020, 6, 15, 8, 0), ValidityZone::ZoneA, None);
+        assert_eq!(
+            report.valid_product.unwrap().product,
+            CardProduct::PeriodPass1
+        );
+
+        let report = card.is_valid_at(helsinki(2020, 6, 15, 8, 0), ValidityZone::ZoneC, None);
+        assert_eq!(report.valid_product.unwrap().product, CardProduct::ETicket);
+    }
+
+    #[test]
+    fn invalidated_e_ticket_should_not_be_valid() {
+        let mut dump = card_dump();
+        dump.e_ticket(
+            0b000_010,
+            dump_datetime(2020, 6, 15, 7, 30),
+            dump_datetime(2020, 6, 15, 8, 50),
+        );
+        dump.e_ticket_validity_status(false);
+        let card = dump.build();
+
+        let report = card.is_valid_at(helsinki(2020, 6, 15, 8, 0), ValidityZone::ZoneC, None);
+        assert!(!report.is_valid());
+        assert!(report.invalid_products.contains(&InvalidProduct {
+            product: CardProduct::ETicket,
+            reason: InvalidReason::Invalidated,
+        }));
+    }
+}